@@ -0,0 +1,59 @@
+use bytes::Bytes;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::config::FireboardAccount;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountState {
+    /// the last poll of the fireboard api worked
+    Ok,
+    /// logging in or the last poll failed, see `error`
+    Error,
+}
+
+/// How an account is doing, published (retained) to
+/// `<base topic>/bridge/accounts/<namespace or default>` after every poll
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountStatus {
+    pub state: AccountState,
+    pub namespace: Option<String>,
+    /// devices the account's last poll returned, after exclusions
+    pub devices: usize,
+    pub online_devices: u8,
+    pub timestamp: DateTime<Local>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AccountStatus {
+    pub fn ok(account: &FireboardAccount, devices: usize, online_devices: u8) -> AccountStatus {
+        AccountStatus {
+            state: AccountState::Ok,
+            namespace: account.namespace.clone(),
+            devices,
+            online_devices,
+            timestamp: Local::now(),
+            error: None,
+        }
+    }
+
+    pub fn error(account: &FireboardAccount, error: impl ToString) -> AccountStatus {
+        AccountStatus {
+            state: AccountState::Error,
+            namespace: account.namespace.clone(),
+            devices: 0,
+            online_devices: 0,
+            timestamp: Local::now(),
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+impl From<AccountStatus> for Bytes {
+    fn from(status: AccountStatus) -> Bytes {
+        let json = serde_json::to_string(&status).unwrap();
+        Bytes::from(json)
+    }
+}
@@ -0,0 +1,244 @@
+//! # Channel Alarms
+//!
+//! High/low temperature alarms for a single channel. An alarm only triggers once the
+//! threshold has been crossed for a minimum duration, and only clears once the temperature
+//! is back past the threshold by the hysteresis amount, so a single spiky reading can't
+//! flap it on and off.
+use chrono::{DateTime, Duration, Local};
+
+use crate::config::ChannelOverride;
+use crate::state_store::ChannelSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlarmKind {
+    High,
+    Low,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ChannelAlarm {
+    active: bool,
+    breached_since: Option<DateTime<Local>>,
+}
+
+impl ChannelAlarm {
+    /// Feeds a new reading into the alarm and returns whether it is active
+    pub fn evaluate(
+        &mut self,
+        kind: AlarmKind,
+        temp: f32,
+        threshold: Option<f32>,
+        hysteresis: f32,
+        min_duration: Duration,
+        now: DateTime<Local>,
+    ) -> bool {
+        let Some(threshold) = threshold else {
+            self.reset();
+            return false;
+        };
+
+        let (breached, cleared) = match kind {
+            AlarmKind::High => (temp > threshold, temp < threshold - hysteresis),
+            AlarmKind::Low => (temp < threshold, temp > threshold + hysteresis),
+        };
+
+        if self.active {
+            if cleared {
+                self.reset();
+            }
+        } else if breached {
+            let breached_since = *self.breached_since.get_or_insert(now);
+            self.active = now - breached_since >= min_duration;
+        } else {
+            self.breached_since = None;
+        }
+        self.active
+    }
+
+    fn reset(&mut self) {
+        self.active = false;
+        self.breached_since = None;
+    }
+}
+
+impl AlarmKind {
+    /// used in topics and unique ids for the alarm's threshold number
+    pub fn threshold_name(&self) -> &'static str {
+        match self {
+            AlarmKind::High => "alarm_max",
+            AlarmKind::Low => "alarm_min",
+        }
+    }
+
+    /// used in topics and unique ids for the alarm's binary sensor
+    pub fn state_name(&self) -> &'static str {
+        match self {
+            AlarmKind::High => "alarm_high",
+            AlarmKind::Low => "alarm_low",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AlarmKind::High => "High",
+            AlarmKind::Low => "Low",
+        }
+    }
+
+    pub fn device_class(&self) -> &'static str {
+        match self {
+            AlarmKind::High => "heat",
+            AlarmKind::Low => "cold",
+        }
+    }
+
+    pub fn threshold(&self, settings: &ChannelSettings) -> Option<f32> {
+        match self {
+            AlarmKind::High => settings.alarm_max,
+            AlarmKind::Low => settings.alarm_min,
+        }
+    }
+
+    /// the threshold from the config file, in `CONFIG_UNIT`
+    pub fn configured_threshold(&self, channel_override: &ChannelOverride) -> Option<f32> {
+        match self {
+            AlarmKind::High => channel_override.alarm_max,
+            AlarmKind::Low => channel_override.alarm_min,
+        }
+    }
+
+    pub fn set_threshold(&self, settings: &mut ChannelSettings, threshold: Option<f32>) {
+        match self {
+            AlarmKind::High => settings.alarm_max = threshold,
+            AlarmKind::Low => settings.alarm_min = threshold,
+        }
+    }
+}
+
+pub const ALARM_KINDS: [AlarmKind; 2] = [AlarmKind::High, AlarmKind::Low];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HYSTERESIS: f32 = 5.0;
+    const MIN_DURATION: Duration = Duration::minutes(1);
+
+    /// Feeds readings 30 seconds apart into a high alarm at 225, returning whether it was
+    /// active after each
+    fn high_alarm(readings: &[f32]) -> Vec<bool> {
+        let start = Local::now();
+        let mut alarm = ChannelAlarm::default();
+        readings
+            .iter()
+            .enumerate()
+            .map(|(i, &temp)| {
+                let now = start + Duration::seconds(30 * i as i64);
+                alarm.evaluate(
+                    AlarmKind::High,
+                    temp,
+                    Some(225.0),
+                    HYSTERESIS,
+                    MIN_DURATION,
+                    now,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn triggers_after_the_minimum_duration() {
+        assert_eq!(
+            high_alarm(&[220.0, 230.0, 231.0, 232.0]),
+            [false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn brief_crossing_never_triggers() {
+        // over the threshold for 30 seconds at a time, which isn't long enough
+        assert_eq!(
+            high_alarm(&[230.0, 230.0, 224.0, 230.0, 230.0, 224.0]),
+            [false; 6]
+        );
+    }
+
+    #[test]
+    fn hovering_in_the_hysteresis_band() {
+        // once active it stays active until the temperature is below 220, dipping under
+        // the threshold and coming back doesn't clear it or start it over
+        assert_eq!(
+            high_alarm(&[230.0, 230.0, 230.0, 224.0, 221.0, 226.0, 222.0, 226.0]),
+            [false, false, true, true, true, true, true, true]
+        );
+    }
+
+    #[test]
+    fn rearms_after_clearing() {
+        assert_eq!(
+            high_alarm(&[230.0, 230.0, 230.0, 219.0, 230.0, 230.0, 230.0]),
+            [false, false, true, false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn low_alarm_clears_above_the_hysteresis() {
+        let start = Local::now();
+        let mut alarm = ChannelAlarm::default();
+        let mut evaluate = |secs: i64, temp: f32| {
+            let now = start + Duration::seconds(secs);
+            alarm.evaluate(
+                AlarmKind::Low,
+                temp,
+                Some(200.0),
+                HYSTERESIS,
+                MIN_DURATION,
+                now,
+            )
+        };
+        assert!(!evaluate(0, 190.0));
+        assert!(evaluate(60, 190.0));
+        assert!(evaluate(90, 204.0));
+        assert!(!evaluate(120, 206.0));
+    }
+
+    #[test]
+    fn no_threshold_resets() {
+        let start = Local::now();
+        let mut alarm = ChannelAlarm::default();
+        let minute = Duration::minutes(1);
+        assert!(!alarm.evaluate(
+            AlarmKind::High,
+            230.0,
+            Some(225.0),
+            HYSTERESIS,
+            minute,
+            start
+        ));
+        assert!(alarm.evaluate(
+            AlarmKind::High,
+            230.0,
+            Some(225.0),
+            HYSTERESIS,
+            minute,
+            start + minute
+        ));
+        // clearing the threshold turns it off, and setting it again starts the wait over
+        assert!(!alarm.evaluate(
+            AlarmKind::High,
+            230.0,
+            None,
+            HYSTERESIS,
+            minute,
+            start + minute * 2
+        ));
+        assert!(!alarm.evaluate(
+            AlarmKind::High,
+            230.0,
+            Some(225.0),
+            HYSTERESIS,
+            minute,
+            start + minute * 3
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+//! # Battery Model
+//!
+//! Fireboard only reports a battery percentage, and only every few minutes with the device
+//! log. Keeping a few hours of those readings gives a discharge rate, and from that an
+//! estimate of how long the battery will last. A percentage that goes up means the device
+//! has been plugged in.
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, Local};
+
+use crate::channel_history::linear_fit;
+
+/// how far back readings are used for the discharge rate
+const BATTERY_HISTORY_HOURS: i64 = 6;
+
+/// the fewest readings needed before a discharge rate is calculated
+const MIN_BATTERY_SAMPLES: usize = 3;
+
+/// how many percentage points the battery has to go up by to count as charging, the
+/// reported percentage wobbles a little on its own
+const CHARGING_MIN_RISE: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy)]
+struct BatterySample {
+    time: DateTime<Local>,
+    percentage: f32,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct BatteryModel {
+    samples: VecDeque<BatterySample>,
+    charging: bool,
+}
+
+impl BatteryModel {
+    /// Adds a battery reading, readings with the same time as the last one are ignored
+    /// since the device log is only updated every few minutes
+    pub fn push(&mut self, time: DateTime<Local>, percentage: f32) {
+        let sample = BatterySample { time, percentage };
+        let Some(last) = self.samples.back().copied() else {
+            self.samples.push_back(sample);
+            return;
+        };
+        if time <= last.time {
+            return;
+        }
+
+        if percentage >= last.percentage + CHARGING_MIN_RISE {
+            // readings from before being plugged in say nothing about discharging later
+            self.charging = true;
+            self.samples.clear();
+        } else if percentage < last.percentage && self.charging {
+            self.charging = false;
+            self.samples.retain(|sample| sample.time == last.time);
+        }
+        self.samples.push_back(sample);
+
+        while let Some(oldest) = self.samples.front() {
+            if time - oldest.time > Duration::hours(BATTERY_HISTORY_HOURS) {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn charging(&self) -> bool {
+        self.charging
+    }
+
+    /// percentage points lost per hour, `None` until there are enough readings or while
+    /// the battery isn't going down
+    pub fn discharge_rate_per_hour(&self) -> Option<f32> {
+        if self.charging || self.samples.len() < MIN_BATTERY_SAMPLES {
+            return None;
+        }
+        let first = self.samples.front()?.time;
+        let points: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .map(|sample| {
+                (
+                    (sample.time - first).num_seconds() as f64 / 3600.0,
+                    sample.percentage as f64,
+                )
+            })
+            .collect();
+        let (slope, _) = linear_fit(&points)?;
+        (slope < 0.0).then_some(-slope as f32)
+    }
+
+    /// hours until the battery is empty at the current discharge rate
+    pub fn runtime_remaining_hours(&self) -> Option<f32> {
+        let percentage = self.samples.back()?.percentage;
+        self.discharge_rate_per_hour().map(|rate| percentage / rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds readings 10 minutes apart from `start`, like the device log
+    fn feed(model: &mut BatteryModel, start: DateTime<Local>, percentages: &[f32]) {
+        for (i, percentage) in percentages.iter().enumerate() {
+            model.push(start + Duration::minutes(i as i64 * 10), *percentage);
+        }
+    }
+
+    #[test]
+    fn runtime_from_the_discharge_rate() {
+        let mut model = BatteryModel::default();
+        // 1% every 10 minutes
+        feed(&mut model, Local::now(), &[80.0, 79.0, 78.0, 77.0]);
+        assert!((model.discharge_rate_per_hour().unwrap() - 6.0).abs() < 0.01);
+        assert!((model.runtime_remaining_hours().unwrap() - 77.0 / 6.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn repeated_device_log_is_ignored() {
+        let start = Local::now();
+        let mut model = BatteryModel::default();
+        feed(&mut model, start, &[80.0, 79.0, 78.0]);
+        // the same device log again, with a reading that would look like charging
+        model.push(start + Duration::minutes(20), 90.0);
+        assert!(!model.charging());
+        assert!((model.discharge_rate_per_hour().unwrap() - 6.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn wobble_is_not_charging() {
+        let mut model = BatteryModel::default();
+        feed(&mut model, Local::now(), &[80.0, 79.0, 79.5, 78.0]);
+        assert!(!model.charging());
+        assert!(model.discharge_rate_per_hour().is_some());
+    }
+
+    #[test]
+    fn discharge_after_charging_starts_over() {
+        let start = Local::now();
+        let mut model = BatteryModel::default();
+        // draining fast, then plugged in
+        feed(&mut model, start, &[60.0, 50.0, 40.0, 45.0, 55.0]);
+        assert!(model.charging());
+        assert!(model.runtime_remaining_hours().is_none());
+
+        // unplugged at 55%, now draining 1% every 10 minutes
+        feed(
+            &mut model,
+            start + Duration::minutes(50),
+            &[55.0, 54.0, 53.0],
+        );
+        assert!(!model.charging());
+        assert!((model.discharge_rate_per_hour().unwrap() - 6.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn only_the_last_hours_count() {
+        let start = Local::now();
+        let mut model = BatteryModel::default();
+        // 2% every 10 minutes for an hour, then 1% every 10 minutes for 6 hours
+        let fast: Vec<f32> = (0..6).map(|i| 100.0 - i as f32 * 2.0).collect();
+        let slow: Vec<f32> = (0..=36).map(|i| 90.0 - i as f32).collect();
+        feed(&mut model, start, &fast);
+        feed(&mut model, start + Duration::minutes(60), &slow);
+        assert!((model.discharge_rate_per_hour().unwrap() - 6.0).abs() < 0.01);
+    }
+}
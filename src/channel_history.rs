@@ -0,0 +1,199 @@
+//! # Channel History
+//!
+//! A short rolling history of readings for a single channel. Derived sensors (like the
+//! rate of change) are calculated from this rather than from the last two readings, since
+//! the 20-40 second poll cadence and the cloud api occasionally repeating a stale reading
+//! make reading-to-reading differences far too noisy.
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, Local};
+
+/// the fewest readings needed before a trend is calculated
+const MIN_TREND_SAMPLES: usize = 3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TempSample {
+    pub time: DateTime<Local>,
+    pub temp: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelHistory {
+    samples: VecDeque<TempSample>,
+    retention: Duration,
+}
+
+impl ChannelHistory {
+    pub fn new(retention: Duration) -> ChannelHistory {
+        ChannelHistory {
+            samples: VecDeque::new(),
+            retention,
+        }
+    }
+
+    pub fn push(&mut self, time: DateTime<Local>, temp: f32) {
+        self.samples.push_back(TempSample { time, temp });
+        while let Some(oldest) = self.samples.front() {
+            if time - oldest.time > self.retention {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Changes how long readings are kept. A longer retention fills up from the readings
+    /// already kept, a shorter one drops the readings it no longer covers straight away.
+    pub fn set_retention(&mut self, retention: Duration) {
+        self.retention = retention;
+        let Some(latest) = self.samples.back().map(|sample| sample.time) else {
+            return;
+        };
+        while let Some(oldest) = self.samples.front() {
+            if latest - oldest.time > self.retention {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn samples_since(&self, since: DateTime<Local>) -> impl Iterator<Item = &TempSample> {
+        self.samples
+            .iter()
+            .filter(move |sample| sample.time >= since)
+    }
+
+    /// The least squares slope of the readings in the window, in degrees per minute.
+    /// Returns `None` until there are enough readings spread over at least half the window.
+    pub fn rate_per_minute(&self, window: Duration, now: DateTime<Local>) -> Option<f32> {
+        let samples: Vec<&TempSample> = self.samples_since(now - window).collect();
+        if samples.len() < MIN_TREND_SAMPLES {
+            return None;
+        }
+        let first = samples.first()?.time;
+        let span = samples.last()?.time - first;
+        if span < window / 2 {
+            return None;
+        }
+
+        let points: Vec<(f64, f64)> = samples
+            .iter()
+            .map(|sample| {
+                let minutes = (sample.time - first).num_milliseconds() as f64 / 60_000.0;
+                (minutes, sample.temp as f64)
+            })
+            .collect();
+        linear_fit(&points).map(|(slope, _)| slope as f32)
+    }
+}
+
+/// Ordinary least squares fit of `y = slope * x + intercept`, returns `(slope, intercept)`
+pub fn linear_fit(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (x, y) in points {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance += (x - mean_x).powi(2);
+    }
+    if variance == 0.0 {
+        return None;
+    }
+    let slope = covariance / variance;
+    Some((slope, mean_y - slope * mean_x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// readings 30 seconds apart
+    fn history(temps: impl IntoIterator<Item = f32>) -> (ChannelHistory, DateTime<Local>) {
+        let start = Local::now();
+        let mut history = ChannelHistory::new(Duration::minutes(10));
+        let mut now = start;
+        for (i, temp) in temps.into_iter().enumerate() {
+            now = start + Duration::seconds(i as i64 * 30);
+            history.push(now, temp);
+        }
+        (history, now)
+    }
+
+    #[test]
+    fn noisy_readings_follow_the_trend() {
+        // climbing 1 degree a minute, with every other reading 2 degrees off either way.
+        // Reading to reading that's anywhere from -3 to +5 degrees a minute
+        let (history, now) = history((0..11).map(|i| {
+            let noise = if i % 2 == 0 { 2.0 } else { -2.0 };
+            150.0 + i as f32 * 0.5 + noise
+        }));
+        let rate = history.rate_per_minute(Duration::minutes(5), now).unwrap();
+        assert!((rate - 1.0).abs() < 0.5, "rate was {}", rate);
+    }
+
+    #[test]
+    fn repeated_stale_readings() {
+        // the api repeating the previous reading every other poll halves reading to reading
+        // differences but barely moves the fitted rate
+        let (history, now) = history((0..11).map(|i| 150.0 + (i / 2 * 2) as f32 * 0.5));
+        let rate = history.rate_per_minute(Duration::minutes(5), now).unwrap();
+        assert!((rate - 1.0).abs() < 0.2, "rate was {}", rate);
+    }
+
+    #[test]
+    fn only_the_window_counts() {
+        // a fast climb 10 minutes ago, then flat for the last 5
+        let (history, now) = history((0..21).map(|i| {
+            if i < 10 {
+                100.0 + i as f32 * 10.0
+            } else {
+                190.0
+            }
+        }));
+        assert_eq!(
+            history.rate_per_minute(Duration::minutes(5), now),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn waits_for_half_the_window() {
+        let window = Duration::minutes(5);
+        // 2 minutes of readings, short of the 2.5 the window needs
+        let (short, now) = history((0..5).map(|i| 150.0 + i as f32));
+        assert!(short.rate_per_minute(window, now).is_none());
+        let (enough, now) = history((0..6).map(|i| 150.0 + i as f32));
+        assert!(enough.rate_per_minute(window, now).is_some());
+    }
+
+    #[test]
+    fn retention_changes_apply_to_kept_readings() {
+        let start = Local::now();
+        let mut history = ChannelHistory::new(Duration::minutes(10));
+        for i in 0..20 {
+            history.push(start + Duration::seconds(i * 30), 200.0);
+        }
+        assert_eq!(history.samples_since(start).count(), 20);
+
+        // a shorter window drops what it doesn't cover without waiting for the next reading
+        history.set_retention(Duration::minutes(5));
+        let latest = start + Duration::seconds(19 * 30);
+        assert_eq!(history.samples_since(start).count(), 11);
+        assert!(history
+            .samples_since(start)
+            .all(|sample| latest - sample.time <= Duration::minutes(5)));
+
+        // a longer one keeps readings past the old window
+        history.set_retention(Duration::minutes(30));
+        for i in 20..40 {
+            history.push(start + Duration::seconds(i * 30), 200.0);
+        }
+        assert_eq!(history.samples_since(start).count(), 31);
+    }
+}
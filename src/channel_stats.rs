@@ -0,0 +1,64 @@
+//! # Channel Statistics
+//!
+//! Min/max/mean of a channel's readings, over the configured windows. Short windows are
+//! worked out from the channel history, the cook window is kept as a running total since
+//! a cook can easily outlast any history we'd want to keep.
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::channel_history::TempSample;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Stats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+/// (name, label) of each statistic, for the optional separate entities
+pub const STAT_NAMES: [(&str, &str); 3] = [("min", "Min"), ("max", "Max"), ("mean", "Mean")];
+
+/// keyed by the window name, e.g. `5m` or `cook`
+pub type ChannelStats = BTreeMap<String, Option<Stats>>;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RunningStats {
+    min: f32,
+    max: f32,
+    total: f64,
+    count: u32,
+}
+
+impl RunningStats {
+    pub fn push(&mut self, value: f32) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.total += value as f64;
+        self.count += 1;
+    }
+
+    pub fn stats(&self) -> Option<Stats> {
+        (self.count > 0).then(|| Stats {
+            min: self.min,
+            max: self.max,
+            mean: round_tenth((self.total / self.count as f64) as f32),
+        })
+    }
+}
+
+/// statistics for a set of readings, `None` if there aren't any
+pub fn sample_stats<'a>(samples: impl Iterator<Item = &'a TempSample>) -> Option<Stats> {
+    let mut running = RunningStats::default();
+    samples.for_each(|sample| running.push(sample.temp));
+    running.stats()
+}
+
+fn round_tenth(value: f32) -> f32 {
+    (value * 10.0).round() / 10.0
+}
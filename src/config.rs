@@ -1,14 +1,31 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::process;
-use serde::Serialize;
-use twelf::{config, Layer};
-use log::{debug, error, info};
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use chrono::Duration;
+use twelf::{config, reexports::clap::{Arg, ArgMatches, Command}, Layer};
+use log::{debug, error, info, warn};
 use url::Url;
 
+use crate::alarm::AlarmKind;
+use crate::constants::FIREBOARD_API_REQUESTS_PER_HOUR;
+use crate::fireboard_api::DegreeType;
+use crate::ha_addon;
+use crate::temperature::{self, Temperature};
+
+/// the unit every temperature, difference and rate in the config is set in, whatever unit
+/// the devices or the output are in
+pub const CONFIG_UNIT: DegreeType = DegreeType::Fahrenheit;
+
 struct ConfigDefaults {}
 impl ConfigDefaults {
     pub fn fireboard_enable_drive_default() -> bool {
         false
     }
+    pub fn has_entity_name_default() -> bool {
+        false
+    }
     pub fn mqtt_url_default() -> String {
         "mqtt://localhost:1883".to_string()
     }
@@ -21,6 +38,93 @@ impl ConfigDefaults {
     pub fn mqtt_clientid_default() -> String {
         "fireboard2mqtt".to_string()
     }
+    pub fn state_dir_default() -> String {
+        "./state".to_string()
+    }
+    pub fn alarm_hysteresis_default() -> f32 {
+        2.0
+    }
+    pub fn alarm_min_duration_secs_default() -> u64 {
+        60
+    }
+    pub fn rate_window_secs_default() -> u64 {
+        300
+    }
+    pub fn rate_smoothing_default() -> f32 {
+        0.5
+    }
+    pub fn eta_window_secs_default() -> u64 {
+        1800
+    }
+    pub fn stall_temp_min_default() -> f32 {
+        150.0
+    }
+    pub fn stall_temp_max_default() -> f32 {
+        175.0
+    }
+    pub fn stall_max_rate_default() -> f32 {
+        4.0
+    }
+    pub fn stall_window_secs_default() -> u64 {
+        1200
+    }
+    pub fn stall_min_duration_secs_default() -> u64 {
+        1200
+    }
+    pub fn cook_start_temp_default() -> f32 {
+        150.0
+    }
+    pub fn cook_end_delay_secs_default() -> u64 {
+        900
+    }
+    pub fn fire_out_window_secs_default() -> u64 {
+        1200
+    }
+    pub fn fire_out_min_fall_rate_default() -> f32 {
+        15.0
+    }
+    pub fn fire_out_drive_saturation_default() -> f32 {
+        0.95
+    }
+    pub fn lid_open_drop_default() -> f32 {
+        20.0
+    }
+    pub fn lid_open_window_secs_default() -> u64 {
+        120
+    }
+    pub fn battery_low_threshold_default() -> u8 {
+        20
+    }
+    pub fn stats_windows_default() -> String {
+        "5m,1h,cook".to_string()
+    }
+    pub fn stats_entities_default() -> bool {
+        false
+    }
+    pub fn reading_min_default() -> f32 {
+        -40.0
+    }
+    pub fn reading_max_default() -> f32 {
+        1000.0
+    }
+    pub fn reading_max_jump_default() -> f32 {
+        50.0
+    }
+    pub fn poll_interval_secs_default() -> u64 {
+        20
+    }
+    pub fn drive_poll_interval_secs_default() -> u64 {
+        40
+    }
+    pub fn idle_poll_interval_secs_default() -> u64 {
+        60
+    }
+    pub fn expires_after_secs_default() -> u32 {
+        600
+    }
+    pub fn devicelog_update_interval_secs_default() -> i64 {
+        300
+    }
     pub fn none_default() -> Option<String> {
         None
     }
@@ -34,6 +138,12 @@ pub struct FireboardConfigEnv { // vis modifiers work too
     pub fireboardaccount_email: Option<String>,
     /// Will use `FB2MQTT_FIREBOARDACCOUNT_PASSWORD`
     pub fireboardaccount_password: Option<String>,
+    /// Will use `FB2MQTT_FIREBOARDACCOUNT_PASSWORD_FILE`
+    pub fireboardaccount_password_file: Option<PathBuf>,
+    /// Will use `FB2MQTT_FIREBOARD_API_TOKEN`
+    pub fireboard_api_token: Option<String>,
+    /// Will use `FB2MQTT_FIREBOARD_API_TOKEN_FILE`
+    pub fireboard_api_token_file: Option<PathBuf>,
     /// Will use `FB2MQTT_FIREBOARD_ENABLE_DRIVE`
     #[serde(default = "ConfigDefaults::fireboard_enable_drive_default")]
     pub fireboard_enable_drive: bool,
@@ -47,6 +157,9 @@ pub struct FireboardConfigEnv { // vis modifiers work too
     /// Will use `FB2MQTT_MQTT_BASE_TOPIC`
     #[serde(default = "ConfigDefaults::mqtt_base_topic_default")]
     pub mqtt_base_topic: String,
+    /// Will use `FB2MQTT_HAS_ENTITY_NAME`
+    #[serde(default = "ConfigDefaults::has_entity_name_default")]
+    pub has_entity_name: bool,
 
 
     /// Will use `FB2MQTT_MQTT_USERNAME`
@@ -55,9 +168,120 @@ pub struct FireboardConfigEnv { // vis modifiers work too
     /// Will use `FB2MQTT_MQTT_PASSWORD`
     #[serde(default = "ConfigDefaults::none_default")]
     pub mqtt_password: Option<String>,
+    /// Will use `FB2MQTT_MQTT_PASSWORD_FILE`
+    pub mqtt_password_file: Option<PathBuf>,
     /// Will use `FB2MQTT_MQTT_CLIENTID`
     #[serde(default = "ConfigDefaults::mqtt_clientid_default")]
     pub mqtt_clientid: String,
+
+    /// Will use `FB2MQTT_TEMPERATURE_UNIT`
+    #[serde(default = "ConfigDefaults::none_default")]
+    pub temperature_unit: Option<String>,
+
+    /// Will use `FB2MQTT_STATE_DIR`
+    #[serde(default = "ConfigDefaults::state_dir_default")]
+    pub state_dir: String,
+    /// Will use `FB2MQTT_ALARM_HYSTERESIS`
+    #[serde(default = "ConfigDefaults::alarm_hysteresis_default")]
+    pub alarm_hysteresis: f32,
+    /// Will use `FB2MQTT_ALARM_MIN_DURATION_SECS`
+    #[serde(default = "ConfigDefaults::alarm_min_duration_secs_default")]
+    pub alarm_min_duration_secs: u64,
+    /// Will use `FB2MQTT_RATE_WINDOW_SECS`
+    #[serde(default = "ConfigDefaults::rate_window_secs_default")]
+    pub rate_window_secs: u64,
+    /// Will use `FB2MQTT_RATE_SMOOTHING`
+    #[serde(default = "ConfigDefaults::rate_smoothing_default")]
+    pub rate_smoothing: f32,
+
+    /// Will use `FB2MQTT_PIT_CHANNEL`
+    pub pit_channel: Option<usize>,
+    /// Will use `FB2MQTT_CHANNEL_TARGETS`
+    #[serde(default = "ConfigDefaults::none_default")]
+    pub channel_targets: Option<String>,
+    /// Will use `FB2MQTT_ETA_WINDOW_SECS`
+    #[serde(default = "ConfigDefaults::eta_window_secs_default")]
+    pub eta_window_secs: u64,
+
+    /// Will use `FB2MQTT_STALL_TEMP_MIN`
+    #[serde(default = "ConfigDefaults::stall_temp_min_default")]
+    pub stall_temp_min: f32,
+    /// Will use `FB2MQTT_STALL_TEMP_MAX`
+    #[serde(default = "ConfigDefaults::stall_temp_max_default")]
+    pub stall_temp_max: f32,
+    /// Will use `FB2MQTT_STALL_MAX_RATE`
+    #[serde(default = "ConfigDefaults::stall_max_rate_default")]
+    pub stall_max_rate: f32,
+    /// Will use `FB2MQTT_STALL_WINDOW_SECS`
+    #[serde(default = "ConfigDefaults::stall_window_secs_default")]
+    pub stall_window_secs: u64,
+    /// Will use `FB2MQTT_STALL_MIN_DURATION_SECS`
+    #[serde(default = "ConfigDefaults::stall_min_duration_secs_default")]
+    pub stall_min_duration_secs: u64,
+
+    /// Will use `FB2MQTT_COOK_START_TEMP`
+    #[serde(default = "ConfigDefaults::cook_start_temp_default")]
+    pub cook_start_temp: f32,
+    /// Will use `FB2MQTT_COOK_END_DELAY_SECS`
+    #[serde(default = "ConfigDefaults::cook_end_delay_secs_default")]
+    pub cook_end_delay_secs: u64,
+
+    /// Will use `FB2MQTT_FIRE_OUT_WINDOW_SECS`
+    #[serde(default = "ConfigDefaults::fire_out_window_secs_default")]
+    pub fire_out_window_secs: u64,
+    /// Will use `FB2MQTT_FIRE_OUT_MIN_FALL_RATE`
+    #[serde(default = "ConfigDefaults::fire_out_min_fall_rate_default")]
+    pub fire_out_min_fall_rate: f32,
+    /// Will use `FB2MQTT_FIRE_OUT_DRIVE_SATURATION`
+    #[serde(default = "ConfigDefaults::fire_out_drive_saturation_default")]
+    pub fire_out_drive_saturation: f32,
+
+    /// Will use `FB2MQTT_LID_OPEN_DROP`
+    #[serde(default = "ConfigDefaults::lid_open_drop_default")]
+    pub lid_open_drop: f32,
+    /// Will use `FB2MQTT_LID_OPEN_WINDOW_SECS`
+    #[serde(default = "ConfigDefaults::lid_open_window_secs_default")]
+    pub lid_open_window_secs: u64,
+
+    /// Will use `FB2MQTT_BATTERY_LOW_THRESHOLD`
+    #[serde(default = "ConfigDefaults::battery_low_threshold_default")]
+    pub battery_low_threshold: u8,
+
+    /// Will use `FB2MQTT_STATS_WINDOWS`
+    #[serde(default = "ConfigDefaults::stats_windows_default")]
+    pub stats_windows: String,
+    /// Will use `FB2MQTT_STATS_ENTITIES`
+    #[serde(default = "ConfigDefaults::stats_entities_default")]
+    pub stats_entities: bool,
+
+    /// Will use `FB2MQTT_READING_MIN`
+    #[serde(default = "ConfigDefaults::reading_min_default")]
+    pub reading_min: f32,
+    /// Will use `FB2MQTT_READING_MAX`
+    #[serde(default = "ConfigDefaults::reading_max_default")]
+    pub reading_max: f32,
+    /// Will use `FB2MQTT_READING_MAX_JUMP`
+    #[serde(default = "ConfigDefaults::reading_max_jump_default")]
+    pub reading_max_jump: f32,
+    /// Will use `FB2MQTT_POLL_INTERVAL_SECS`
+    #[serde(default = "ConfigDefaults::poll_interval_secs_default")]
+    pub poll_interval_secs: u64,
+    /// Will use `FB2MQTT_DRIVE_POLL_INTERVAL_SECS`
+    #[serde(default = "ConfigDefaults::drive_poll_interval_secs_default")]
+    pub drive_poll_interval_secs: u64,
+    /// Will use `FB2MQTT_IDLE_POLL_INTERVAL_SECS`
+    #[serde(default = "ConfigDefaults::idle_poll_interval_secs_default")]
+    pub idle_poll_interval_secs: u64,
+    /// Will use `FB2MQTT_EXPIRES_AFTER_SECS`
+    #[serde(default = "ConfigDefaults::expires_after_secs_default")]
+    pub expires_after_secs: u32,
+    /// Will use `FB2MQTT_DEVICELOG_UPDATE_INTERVAL_SECS`
+    #[serde(default = "ConfigDefaults::devicelog_update_interval_secs_default")]
+    pub devicelog_update_interval_secs: i64,
+    /// Per device overrides keyed by hardware id or uuid, only from the config file
+    pub devices: Option<HashMap<String, DeviceOverride>>,
+    /// More fireboard accounts to poll, only from the config file
+    pub accounts: Option<Vec<AccountConfig>>,
 }
 
 // impl Default for FireboardConfigEnv {
@@ -77,78 +301,932 @@ pub struct FireboardConfigEnv { // vis modifiers work too
 // 
 // }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct MqttCredentials {
     pub username: String,
     #[serde(skip_serializing)]
     pub password: String,
 }
 
+/// A target temperature for a channel, either on one device or on every device
 #[derive(Debug, Clone, Serialize)]
-pub struct Fb2MqttConfig {
-    pub fireboardaccount_email: String,
+pub struct ChannelTarget {
+    pub hardware_id: Option<String>,
+    pub channel: usize,
+    pub target: f32,
+}
+
+impl ChannelTarget {
+    /// Parses a comma separated list of `[<hardware_id>:]<channel>=<target>` entries
+    pub fn parse_list(list: &str) -> Result<Vec<ChannelTarget>, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (channel, target) = entry
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected <channel>=<target> but got '{}'", entry))?;
+                let (hardware_id, channel) = match channel.split_once(':') {
+                    Some((hardware_id, channel)) => (Some(hardware_id.trim().to_string()), channel),
+                    None => (None, channel),
+                };
+                Ok(ChannelTarget {
+                    hardware_id,
+                    channel: channel
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("invalid channel in '{}': {}", entry, e))?,
+                    target: target
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("invalid target in '{}': {}", entry, e))?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// An extra fireboard account, from an `[[accounts]]` table in the config file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccountConfig {
+    pub email: Option<String>,
+    pub password: Option<String>,
+    pub password_file: Option<PathBuf>,
+    /// a token issued beforehand, used instead of logging in with the email and password
+    pub api_token: Option<String>,
+    pub api_token_file: Option<PathBuf>,
+    /// topic level the account's devices are published under, e.g.
+    /// `fireboard2mqtt/<namespace>/<hardware_id>/...`
+    pub namespace: Option<String>,
+}
+
+/// A fireboard account the bridge polls, each with its own api client and rate budget.
+/// Has an api token, an email and password, or both, in which case the password is only
+/// used if the token is rejected.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FireboardAccount {
+    pub email: Option<String>,
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    #[serde(skip_serializing)]
+    pub api_token: Option<String>,
+    pub namespace: Option<String>,
+}
+
+impl FireboardAccount {
+    /// how the account is referred to in logs and its status topic
+    pub fn name(&self) -> &str {
+        self.namespace.as_deref().unwrap_or("default")
+    }
+}
+
+/// Overrides for one device, from a `[devices.<hardware_id or uuid>]` table in the config file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceOverride {
+    /// leave the device out of home assistant completely
+    #[serde(default)]
+    pub exclude: bool,
+    /// the area home assistant suggests for the device when it's first discovered
+    pub suggested_area: Option<String>,
+    #[serde(default)]
+    pub channels: BTreeMap<usize, ChannelOverride>,
+}
+
+/// Overrides for one channel of a device, from `[devices.<id>.channels.<n>]`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelOverride {
+    /// leave the channel out of home assistant
+    #[serde(default)]
+    pub hidden: bool,
+    /// used instead of the channel label set in the Fireboard app
+    pub name: Option<String>,
+    /// icon for the channel's temperature sensor, e.g. `mdi:food-steak`
+    pub icon: Option<String>,
+    /// alarm thresholds (in °F) used until one is set over mqtt
+    #[serde(alias = "min")]
+    pub alarm_min: Option<f32>,
+    #[serde(alias = "max")]
+    pub alarm_max: Option<f32>,
+}
+
+/// A window channel statistics are calculated over, either a fixed duration or the cook
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsWindow {
+    /// as configured, e.g. `5m`, used in attribute names and unique ids
+    pub name: String,
+    /// `None` for the cook window
     #[serde(skip_serializing)]
-    pub fireboardaccount_password: String,
+    pub duration: Option<Duration>,
+}
+
+impl StatsWindow {
+    /// Parses a comma separated list of windows, each either `cook` or a number followed
+    /// by `s`, `m` or `h`
+    pub fn parse_list(list: &str) -> Result<Vec<StatsWindow>, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                if entry.eq_ignore_ascii_case("cook") {
+                    return Ok(StatsWindow {
+                        name: "cook".to_string(),
+                        duration: None,
+                    });
+                }
+                let suffix_len = entry.chars().last().map_or(0, char::len_utf8);
+                let (amount, suffix) = entry.split_at(entry.len() - suffix_len);
+                let amount = amount
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|amount| *amount > 0)
+                    .ok_or_else(|| format!("invalid window '{}'", entry))?;
+                let duration = match suffix {
+                    "s" => Duration::seconds(amount),
+                    "m" => Duration::minutes(amount),
+                    "h" => Duration::hours(amount),
+                    _ => return Err(format!("invalid window '{}', expected s, m or h", entry)),
+                };
+                Ok(StatsWindow {
+                    name: entry.to_string(),
+                    duration: Some(duration),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Fb2MqttConfig {
+    pub accounts: Vec<FireboardAccount>,
     pub fireboard_enable_drive: bool,
     pub mqtt_host: String,
     pub mqtt_port: u16,
     pub mqtt_discovery_topic: String,
     pub mqtt_base_topic: String,
+    /// name entities after their device in home assistant, see `MQTTDiscoveryEntity`
+    pub has_entity_name: bool,
     pub mqtt_credentials: Option<MqttCredentials>,
     pub mqtt_clientid: String,
+    /// publish temperatures in this unit instead of each device's own unit
+    pub temperature_unit: Option<DegreeType>,
+    pub state_dir: String,
+    // every temperature below is in `CONFIG_UNIT`, use `temperatures` and `channel_target`
+    // to get them in a device's output unit
+    pub alarm_hysteresis: f32,
+    pub alarm_min_duration_secs: u64,
+    pub rate_window_secs: u64,
+    pub rate_smoothing: f32,
+    pub pit_channel: Option<usize>,
+    pub channel_targets: Vec<ChannelTarget>,
+    pub eta_window_secs: u64,
+    pub stall_temp_min: f32,
+    pub stall_temp_max: f32,
+    /// per hour
+    pub stall_max_rate: f32,
+    pub stall_window_secs: u64,
+    pub stall_min_duration_secs: u64,
+    pub cook_start_temp: f32,
+    pub cook_end_delay_secs: u64,
+    pub fire_out_window_secs: u64,
+    /// per hour
+    pub fire_out_min_fall_rate: f32,
+    pub fire_out_drive_saturation: f32,
+    pub lid_open_drop: f32,
+    pub lid_open_window_secs: u64,
+    /// percentage
+    pub battery_low_threshold: u8,
+    pub stats_windows: Vec<StatsWindow>,
+    pub stats_entities: bool,
+    /// the plausible range and largest believable jump
+    pub reading_min: f32,
+    pub reading_max: f32,
+    pub reading_max_jump: f32,
+    /// how long to wait between polls of the fireboard api with devices online, with drive
+    /// support enabled, and with every device offline
+    pub poll_interval_secs: u64,
+    pub drive_poll_interval_secs: u64,
+    pub idle_poll_interval_secs: u64,
+    /// how long home assistant keeps a channel or drive reading before it expires, None to
+    /// never expire them
+    pub expires_after_secs: Option<u32>,
+    /// how long after its last device log a device without readings still counts as online
+    pub devicelog_update_interval_secs: i64,
+    pub devices: HashMap<String, DeviceOverride>,
+}
+
+/// The config's temperatures converted from `CONFIG_UNIT` into a device's output unit
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigTemperatures {
+    pub alarm_hysteresis: f32,
+    pub stall_temp_min: f32,
+    pub stall_temp_max: f32,
+    pub stall_max_rate: f32,
+    pub cook_start_temp: f32,
+    pub fire_out_min_fall_rate: f32,
+    pub lid_open_drop: f32,
+    pub reading_min: f32,
+    pub reading_max: f32,
+    pub reading_max_jump: f32,
+}
+
+/// a temperature from the config in `unit`
+fn config_temperature(value: f32, unit: DegreeType) -> f32 {
+    Temperature::new(value, CONFIG_UNIT).to_unit(unit).value()
+}
+
+/// a temperature difference or rate from the config in `unit`
+fn config_delta(delta: f32, unit: DegreeType) -> f32 {
+    temperature::convert_delta(delta, CONFIG_UNIT, unit)
+}
+
+impl Fb2MqttConfig {
+    /// every temperature from the config, in `unit`
+    pub fn temperatures(&self, unit: DegreeType) -> ConfigTemperatures {
+        ConfigTemperatures {
+            alarm_hysteresis: config_delta(self.alarm_hysteresis, unit),
+            stall_temp_min: config_temperature(self.stall_temp_min, unit),
+            stall_temp_max: config_temperature(self.stall_temp_max, unit),
+            stall_max_rate: config_delta(self.stall_max_rate, unit),
+            cook_start_temp: config_temperature(self.cook_start_temp, unit),
+            fire_out_min_fall_rate: config_delta(self.fire_out_min_fall_rate, unit),
+            lid_open_drop: config_delta(self.lid_open_drop, unit),
+            reading_min: config_temperature(self.reading_min, unit),
+            reading_max: config_temperature(self.reading_max, unit),
+            reading_max_jump: config_delta(self.reading_max_jump, unit),
+        }
+    }
+
+    /// a channel's configured alarm threshold, in `unit`
+    pub fn alarm_threshold(
+        &self,
+        hardware_id: &str,
+        uuid: &str,
+        channel: usize,
+        kind: AlarmKind,
+        unit: DegreeType,
+    ) -> Option<f32> {
+        self.channel_override(hardware_id, uuid, channel)
+            .and_then(|channel_override| kind.configured_threshold(channel_override))
+            .map(|threshold| config_temperature(threshold, unit))
+    }
+
+    /// whether moving to `new` needs a new connection to the mqtt broker
+    pub fn needs_reconnect(&self, new: &Fb2MqttConfig) -> bool {
+        self.mqtt_host != new.mqtt_host
+            || self.mqtt_port != new.mqtt_port
+            || self.mqtt_clientid != new.mqtt_clientid
+            || self.mqtt_credentials != new.mqtt_credentials
+    }
+
+    /// A reloaded config to switch to in place of this one. Settings that decide which
+    /// accounts are polled and where everything is published or saved can't change without
+    /// a restart, so they're kept as they are, with a warning if they were changed.
+    pub fn reloaded(&self, mut new: Fb2MqttConfig) -> Fb2MqttConfig {
+        let warn_if_changed = |name: &str, changed: bool| {
+            if changed {
+                warn!("{} changed, restart the bridge to apply it", name);
+            }
+        };
+        warn_if_changed("accounts", self.accounts != new.accounts);
+        warn_if_changed("FB2MQTT_FIREBOARD_ENABLE_DRIVE", self.fireboard_enable_drive != new.fireboard_enable_drive);
+        warn_if_changed("FB2MQTT_MQTT_BASE_TOPIC", self.mqtt_base_topic != new.mqtt_base_topic);
+        warn_if_changed("FB2MQTT_MQTT_DISCOVERY_TOPIC", self.mqtt_discovery_topic != new.mqtt_discovery_topic);
+        warn_if_changed("FB2MQTT_TEMPERATURE_UNIT", self.temperature_unit != new.temperature_unit);
+        warn_if_changed("FB2MQTT_STATE_DIR", self.state_dir != new.state_dir);
+        new.accounts = self.accounts.clone();
+        new.fireboard_enable_drive = self.fireboard_enable_drive;
+        new.mqtt_base_topic = self.mqtt_base_topic.clone();
+        new.mqtt_discovery_topic = self.mqtt_discovery_topic.clone();
+        new.temperature_unit = self.temperature_unit;
+        new.state_dir = self.state_dir.clone();
+        new
+    }
+
+    /// where an account's devices are published, under its namespace if it has one
+    pub fn account_base_topic(&self, account: &FireboardAccount) -> String {
+        match &account.namespace {
+            Some(namespace) => format!("{}/{}", self.mqtt_base_topic, namespace),
+            None => self.mqtt_base_topic.clone(),
+        }
+    }
+
+    /// where an account's settings are saved, in a directory named after its namespace
+    pub fn account_state_dir(&self, account: &FireboardAccount) -> String {
+        match &account.namespace {
+            Some(namespace) => Path::new(&self.state_dir)
+                .join(namespace)
+                .to_string_lossy()
+                .to_string(),
+            None => self.state_dir.clone(),
+        }
+    }
+
+    pub fn account_status_topic(&self, account: &FireboardAccount) -> String {
+        format!("{}/bridge/accounts/{}", self.mqtt_base_topic, account.name())
+    }
+
+    /// the overrides for a device, matched on either its hardware id or uuid
+    pub fn device_override(&self, hardware_id: &str, uuid: &str) -> Option<&DeviceOverride> {
+        self.devices.get(hardware_id).or_else(|| self.devices.get(uuid))
+    }
+
+    /// the overrides for one of a device's channels
+    pub fn channel_override(
+        &self,
+        hardware_id: &str,
+        uuid: &str,
+        channel: usize,
+    ) -> Option<&ChannelOverride> {
+        self.device_override(hardware_id, uuid)
+            .and_then(|device| device.channels.get(&channel))
+    }
+
+    /// the configured target for a channel in `unit`, a device specific target wins over a
+    /// general one
+    pub fn channel_target(&self, hardware_id: &String, channel: usize, unit: DegreeType) -> Option<f32> {
+        self.channel_targets
+            .iter()
+            .filter(|target| target.channel == channel)
+            .filter(|target| {
+                target
+                    .hardware_id
+                    .as_ref()
+                    .is_none_or(|target_hardware_id| target_hardware_id == hardware_id)
+            })
+            .max_by_key(|target| target.hardware_id.is_some())
+            .map(|target| config_temperature(target.target, unit))
+    }
+}
+
+/// The command line interface, `--config <path>` plus a `--<field-name>` flag for every
+/// config field (e.g. `--mqtt-url`), and the `check-config` subcommand
+pub fn cli_command() -> Command {
+    // the device overrides and accounts are nested tables, which only make sense in the
+    // config file
+    let field_args = FireboardConfigEnv::clap_args()
+        .into_iter()
+        .filter(|arg| arg.get_id() != "devices" && arg.get_id() != "accounts")
+        .map(|arg| {
+            // twelf hands the doc comments out in hash map order, so name the env var here
+            let env_var = format!("FB2MQTT_{}", arg.get_id().as_str().replace('-', "_").to_uppercase());
+            arg.help(format!("overrides {} and the config file", env_var)).global(true)
+        });
+
+    Command::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .short('c')
+                .value_name("PATH")
+                .value_parser(twelf::reexports::clap::value_parser!(PathBuf))
+                .help("TOML (.toml) or YAML (.yaml/.yml) config file, overridden by env vars and flags")
+                .global(true),
+        )
+        .args(field_args)
+        .subcommand(
+            Command::new("check-config")
+                .about("Validate the config and exit, with a non-zero exit code if it's invalid"),
+        )
 }
 
-pub fn load_cfg_from_env() -> Fb2MqttConfig {
-    debug!("loading config from env");
-    let loaded_env_config = FireboardConfigEnv::with_layers(&[Layer::Env(Some("FB2MQTT_".to_string()))]).unwrap();
-    
-    let cfg = loaded_env_config;
-    let mut cfg_load_error = false;
-    if cfg.fireboardaccount_email.is_none() {
-        error!("missing required env var FB2MQTT_FIREBOARDACCOUNT_EMAIL");
-        cfg_load_error = true;
+/// Picks the twelf layer for a config file from its extension
+fn config_file_layer(path: &Path) -> Result<Layer, String> {
+    // twelf only reports "io error", so check the file is there first
+    if let Err(err) = std::fs::metadata(path) {
+        return Err(format!("can't read config file {}: {}", path.display(), err));
     }
+    match path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase()) {
+        Some(ext) if ext == "toml" => Ok(Layer::Toml(path.to_path_buf())),
+        Some(ext) if ext == "yaml" || ext == "yml" => Ok(Layer::Yaml(path.to_path_buf())),
+        _ => Err(format!(
+            "unsupported config file {}, expected a .toml, .yaml or .yml file",
+            path.display()
+        )),
+    }
+}
 
-    if cfg.fireboardaccount_password.is_none() {
-        error!("missing required env var FB2MQTT_FIREBOARDACCOUNT_PASSWORD");
-        cfg_load_error = true;
+/// Reads a secret (e.g. a docker or kubernetes secret mounted under `/run/secrets`) from a
+/// file, dropping the trailing newline editors and `echo` leave behind
+fn read_secret_file(path: &Path) -> Result<String, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("can't read {}: {}", path.display(), err))?;
+    let secret = contents.trim_end_matches(['\r', '\n']);
+    if secret.is_empty() {
+        return Err(format!("{} is empty", path.display()));
     }
+    Ok(secret.to_string())
+}
 
-    if cfg.mqtt_username.is_none() {
-        eprintln!("cfg.fb2mqtt_mqtt_username: {:?}", cfg.mqtt_username);
-        info!("missing or empty env var FB2MQTT_MQTT_USERNAME, mqtt will operate in anonymous mode")
+/// Fills in a secret from its file variant, which can't be combined with the plain value
+fn resolve_secret(
+    name: &str,
+    file_name: &str,
+    value: &mut Option<String>,
+    file: Option<&Path>,
+) -> Result<(), String> {
+    let Some(path) = file else {
+        return Ok(());
+    };
+    if value.is_some() {
+        return Err(format!(
+            "both {} and {} are set, only set one",
+            name, file_name
+        ));
     }
+    let secret = read_secret_file(path)
+        .map_err(|err| format!("Error reading {}: {}", file_name, err))?;
+    *value = Some(secret);
+    Ok(())
+}
 
-    let parsed_url = Url::parse(&cfg.mqtt_url);
+/// Checks a topic the bridge publishes under can actually be published to
+fn validate_topic(name: &str, topic: &str) -> Result<(), String> {
+    if topic.is_empty() {
+        return Err(format!("{} can't be empty", name));
+    }
+    if let Some(c) = topic.chars().find(|c| matches!(c, '+' | '#' | '\0')) {
+        return Err(format!(
+            "{} '{}' contains '{}', which isn't allowed in an mqtt topic",
+            name,
+            topic,
+            c.escape_default()
+        ));
+    }
+    if topic.starts_with('/') || topic.ends_with('/') {
+        return Err(format!("{} '{}' can't start or end with '/'", name, topic));
+    }
+    Ok(())
+}
+
+/// Checks an account namespace can be used as a single level of the mqtt topics
+fn validate_namespace(name: &str, namespace: &str) -> Result<(), String> {
+    validate_topic(name, namespace)?;
+    if namespace.contains('/') {
+        return Err(format!("{} '{}' can't contain '/'", name, namespace));
+    }
+    // `<base topic>/bridge` is where the bridge's own status goes
+    if namespace == "bridge" {
+        return Err(format!("{} can't be 'bridge'", name));
+    }
+    Ok(())
+}
+
+/// Parses the broker url into a host and port, rejecting anything rumqttc can't connect to
+fn parse_mqtt_url(mqtt_url: &str) -> Result<(String, u16), String> {
+    let url = Url::parse(mqtt_url)
+        .map_err(|err| format!("Error parsing mqtt url {}: {}", mqtt_url, err))?;
+    if !matches!(url.scheme(), "mqtt" | "tcp") {
+        return Err(format!(
+            "unsupported mqtt url scheme '{}' in {}, expected mqtt://<host>[:<port>]",
+            url.scheme(),
+            mqtt_url
+        ));
+    }
+    let host = url
+        .host_str()
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| format!("mqtt url {} is missing a host", mqtt_url))?;
+    Ok((host.to_string(), url.port().unwrap_or(1883)))
+}
+
+/// Builds the twelf layers, each overriding the one before it: defaults < home assistant
+/// add-on options < config file (`--config`) < `FB2MQTT_*` env vars < command line flags
+async fn load_layers(args: &ArgMatches) -> Result<FireboardConfigEnv, String> {
+    let addon_layer = ha_addon::addon_layer()
+        .await
+        .map_err(|err| format!("Error loading the add-on options: {:#}", err))?;
+    // created after the await, twelf's layers aren't Send
+    let mut layers = vec![];
+    if let Some(addon_layer) = addon_layer {
+        layers.push(Layer::CustomFn((move || addon_layer).into()));
+    }
+    if let Some(path) = args.get_one::<PathBuf>("config") {
+        debug!("loading config file {}", path.display());
+        layers.push(config_file_layer(path)?);
+    }
+    layers.push(Layer::Env(Some("FB2MQTT_".to_string())));
+    layers.push(Layer::Clap(args.clone()));
+
+    FireboardConfigEnv::with_layers(&layers).map_err(|err| format!("Error loading config: {}", err))
+}
+
+/// Loads and validates the config, returning every problem found rather than stopping at
+/// the first one
+pub async fn build_cfg(args: &ArgMatches) -> Result<Fb2MqttConfig, Vec<String>> {
+    debug!("loading config");
+    let mut cfg = load_layers(args).await.map_err(|err| vec![err])?;
 
-    if let Err(err) = parsed_url {
-        error!("Error parsing mqtt url {}: {}", cfg.mqtt_url, err);
-        cfg_load_error = true;
+    let mut errors = vec![];
+    let secrets = [
+        (
+            "FB2MQTT_FIREBOARDACCOUNT_PASSWORD",
+            &mut cfg.fireboardaccount_password,
+            cfg.fireboardaccount_password_file.as_deref(),
+        ),
+        (
+            "FB2MQTT_FIREBOARD_API_TOKEN",
+            &mut cfg.fireboard_api_token,
+            cfg.fireboard_api_token_file.as_deref(),
+        ),
+        (
+            "FB2MQTT_MQTT_PASSWORD",
+            &mut cfg.mqtt_password,
+            cfg.mqtt_password_file.as_deref(),
+        ),
+    ];
+    for (name, value, file) in secrets {
+        if let Err(err) = resolve_secret(name, &format!("{}_FILE", name), value, file) {
+            errors.push(err);
+        }
     }
 
-    if cfg_load_error {
-        process::exit(1);
+    let mut accounts = vec![];
+    // a secret file that couldn't be read has already been reported
+    let secret_file_failed = (cfg.fireboardaccount_password_file.is_some() && cfg.fireboardaccount_password.is_none())
+        || (cfg.fireboard_api_token_file.is_some() && cfg.fireboard_api_token.is_none());
+    match (&cfg.fireboardaccount_email, &cfg.fireboardaccount_password, &cfg.fireboard_api_token) {
+        (Some(_), Some(_), _) | (_, None, Some(_)) => accounts.push(FireboardAccount {
+            email: cfg.fireboardaccount_email,
+            password: cfg.fireboardaccount_password,
+            api_token: cfg.fireboard_api_token,
+            namespace: None,
+        }),
+        (Some(_), None, None) if !secret_file_failed => {
+            errors.push("missing required fireboardaccount_password (FB2MQTT_FIREBOARDACCOUNT_PASSWORD or FB2MQTT_FIREBOARDACCOUNT_PASSWORD_FILE), or FB2MQTT_FIREBOARD_API_TOKEN".to_string());
+        }
+        (None, Some(_), _) => {
+            errors.push("FB2MQTT_FIREBOARDACCOUNT_PASSWORD is set without FB2MQTT_FIREBOARDACCOUNT_EMAIL".to_string());
+        }
+        _ => {}
+    }
+    for (i, mut account) in cfg.accounts.unwrap_or_default().into_iter().enumerate() {
+        let name = format!("accounts[{}]", i);
+        let mut resolved = true;
+        for (field, value, file) in [
+            ("password", &mut account.password, account.password_file.as_deref()),
+            ("api_token", &mut account.api_token, account.api_token_file.as_deref()),
+        ] {
+            if let Err(err) = resolve_secret(
+                &format!("{}.{}", name, field),
+                &format!("{}.{}_file", name, field),
+                value,
+                file,
+            ) {
+                errors.push(err);
+                resolved = false;
+            }
+        }
+        if !resolved {
+            continue;
+        }
+        match (&account.email, &account.password, &account.api_token) {
+            (Some(_), Some(_), _) | (_, None, Some(_)) => {}
+            (Some(email), None, None) => {
+                errors.push(format!("{} ({}) is missing a password, password_file or api_token", name, email));
+                continue;
+            }
+            (None, Some(_), _) => {
+                errors.push(format!("{} has a password but no email", name));
+                continue;
+            }
+            (None, None, None) => {
+                errors.push(format!("{} needs an email and password, or an api_token", name));
+                continue;
+            }
+        }
+        if let Some(namespace) = &account.namespace {
+            if let Err(err) = validate_namespace(&format!("{}.namespace", name), namespace) {
+                errors.push(err);
+            }
+        }
+        accounts.push(FireboardAccount {
+            email: account.email,
+            password: account.password,
+            api_token: account.api_token,
+            namespace: account.namespace,
+        });
     }
 
-    let mqtt_url = parsed_url.unwrap();
+    if accounts.is_empty() && errors.is_empty() {
+        errors.push("missing required fireboardaccount_email (FB2MQTT_FIREBOARDACCOUNT_EMAIL) or FB2MQTT_FIREBOARD_API_TOKEN, or [[accounts]] in the config file".to_string());
+    }
+    // each account's devices are published under its namespace and its settings saved
+    // in its own state dir, so those can't be shared
+    let mut namespaces = HashSet::new();
+    for account in &accounts {
+        if !namespaces.insert(&account.namespace) {
+            errors.push(match &account.namespace {
+                Some(namespace) => format!("more than one account uses the namespace '{}'", namespace),
+                None => "more than one account has no namespace, give all but one of them a namespace".to_string(),
+            });
+        }
+    }
+
+    let mqtt_username = cfg.mqtt_username.filter(|username| !username.is_empty());
+    let mqtt_credentials = match (mqtt_username, cfg.mqtt_password) {
+        (Some(username), Some(password)) => Some(MqttCredentials { username, password }),
+        (Some(_), None) if cfg.mqtt_password_file.is_none() => {
+            errors.push("FB2MQTT_MQTT_USERNAME is set without FB2MQTT_MQTT_PASSWORD (or FB2MQTT_MQTT_PASSWORD_FILE)".to_string());
+            None
+        }
+        (None, Some(_)) => {
+            errors.push("FB2MQTT_MQTT_PASSWORD is set without FB2MQTT_MQTT_USERNAME".to_string());
+            None
+        }
+        _ => None,
+    };
 
-    Fb2MqttConfig {
-        fireboardaccount_email: cfg.fireboardaccount_email.unwrap().to_string(),
-        fireboardaccount_password: cfg.fireboardaccount_password.unwrap().to_string(),
+    let (mqtt_host, mqtt_port) = parse_mqtt_url(&cfg.mqtt_url).unwrap_or_else(|err| {
+        errors.push(err);
+        (String::new(), 0)
+    });
+
+    for (name, topic) in [
+        ("FB2MQTT_MQTT_BASE_TOPIC", &cfg.mqtt_base_topic),
+        ("FB2MQTT_MQTT_DISCOVERY_TOPIC", &cfg.mqtt_discovery_topic),
+    ] {
+        if let Err(err) = validate_topic(name, topic) {
+            errors.push(err);
+        }
+    }
+
+    let channel_targets =
+        ChannelTarget::parse_list(cfg.channel_targets.as_deref().unwrap_or_default())
+            .unwrap_or_else(|err| {
+                errors.push(format!("Error parsing FB2MQTT_CHANNEL_TARGETS: {}", err));
+                vec![]
+            });
+
+    let temperature_unit = match cfg.temperature_unit.as_deref() {
+        None | Some("") | Some("device") => None,
+        Some(unit) => DegreeType::from_str(unit)
+            .map_err(|_| {
+                errors.push(format!(
+                    "invalid FB2MQTT_TEMPERATURE_UNIT '{}', expected C, F or device",
+                    unit
+                ));
+            })
+            .ok(),
+    };
+
+    let stats_windows = StatsWindow::parse_list(&cfg.stats_windows).unwrap_or_else(|err| {
+        errors.push(format!("Error parsing FB2MQTT_STATS_WINDOWS: {}", err));
+        vec![]
+    });
+
+    if cfg.reading_min >= cfg.reading_max {
+        errors.push(format!(
+            "FB2MQTT_READING_MIN ({}) must be below FB2MQTT_READING_MAX ({})",
+            cfg.reading_min, cfg.reading_max
+        ));
+    }
+
+    if cfg.stall_temp_min >= cfg.stall_temp_max {
+        errors.push(format!(
+            "FB2MQTT_STALL_TEMP_MIN ({}) must be below FB2MQTT_STALL_TEMP_MAX ({})",
+            cfg.stall_temp_min, cfg.stall_temp_max
+        ));
+    }
+
+    for (device, device_override) in cfg.devices.iter().flatten() {
+        for (channel, channel_override) in &device_override.channels {
+            if let (Some(alarm_min), Some(alarm_max)) = (channel_override.alarm_min, channel_override.alarm_max) {
+                if alarm_min >= alarm_max {
+                    errors.push(format!(
+                        "devices.{}.channels.{}: alarm_min ({}) must be below alarm_max ({})",
+                        device, channel, alarm_min, alarm_max
+                    ));
+                }
+            }
+        }
+    }
+
+    // every poll lists the devices, and with drive support one more request per device. How
+    // many devices there are isn't known until the first poll, so this assumes one and the
+    // watcher slows down polling if there are more
+    let budget_interval = 3600 / FIREBOARD_API_REQUESTS_PER_HOUR;
+    let drive_requests = if cfg.fireboard_enable_drive { 2 } else { 1 };
+    for (name, interval, requests) in [
+        ("FB2MQTT_POLL_INTERVAL_SECS", cfg.poll_interval_secs, 1),
+        ("FB2MQTT_DRIVE_POLL_INTERVAL_SECS", cfg.drive_poll_interval_secs, 2),
+        ("FB2MQTT_IDLE_POLL_INTERVAL_SECS", cfg.idle_poll_interval_secs, drive_requests),
+    ] {
+        if interval < budget_interval * requests {
+            errors.push(format!(
+                "{} ({}) would go over the fireboard api's limit of {} requests per hour, it must be at least {}",
+                name,
+                interval,
+                FIREBOARD_API_REQUESTS_PER_HOUR,
+                budget_interval * requests
+            ));
+        }
+    }
+
+    // differences and rates that only work one way round, so 0 or negative is a mistake
+    for (name, value) in [
+        ("FB2MQTT_ALARM_HYSTERESIS", cfg.alarm_hysteresis),
+        ("FB2MQTT_STALL_MAX_RATE", cfg.stall_max_rate),
+        ("FB2MQTT_FIRE_OUT_MIN_FALL_RATE", cfg.fire_out_min_fall_rate),
+        ("FB2MQTT_LID_OPEN_DROP", cfg.lid_open_drop),
+        ("FB2MQTT_READING_MAX_JUMP", cfg.reading_max_jump),
+    ] {
+        if value.is_nan() || value <= 0.0 {
+            errors.push(format!("{} ({}) must be above 0", name, value));
+        }
+    }
+
+    for (name, value) in [
+        ("FB2MQTT_RATE_SMOOTHING", cfg.rate_smoothing),
+        ("FB2MQTT_FIRE_OUT_DRIVE_SATURATION", cfg.fire_out_drive_saturation),
+    ] {
+        if !(value > 0.0 && value <= 1.0) {
+            errors.push(format!("{} ({}) must be in (0,1]", name, value));
+        }
+    }
+    if cfg.battery_low_threshold > 100 {
+        errors.push(format!(
+            "FB2MQTT_BATTERY_LOW_THRESHOLD ({}) must be a percentage, at most 100",
+            cfg.battery_low_threshold
+        ));
+    }
+
+    // a reading has to outlive the gap until the next one, with a missed poll to spare
+    let poll_interval = if cfg.fireboard_enable_drive {
+        cfg.drive_poll_interval_secs
+    } else {
+        cfg.poll_interval_secs
+    };
+    if cfg.expires_after_secs != 0 && u64::from(cfg.expires_after_secs) < poll_interval * 2 {
+        errors.push(format!(
+            "FB2MQTT_EXPIRES_AFTER_SECS ({}) must be at least twice the poll interval ({}s), or 0 to never expire readings",
+            cfg.expires_after_secs, poll_interval
+        ));
+    }
+
+    if cfg.devicelog_update_interval_secs <= 0 {
+        errors.push(format!(
+            "FB2MQTT_DEVICELOG_UPDATE_INTERVAL_SECS ({}) must be above 0",
+            cfg.devicelog_update_interval_secs
+        ));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(Fb2MqttConfig {
+        accounts,
         fireboard_enable_drive: cfg
             .fireboard_enable_drive,
-        mqtt_host: mqtt_url.host_str().unwrap().to_string(),
-        mqtt_port: mqtt_url.port().unwrap_or(1883),
+        mqtt_host,
+        mqtt_port,
         mqtt_base_topic: cfg.mqtt_base_topic.to_string(),
         mqtt_discovery_topic: cfg.mqtt_discovery_topic.to_string(),
-        mqtt_credentials: if cfg.mqtt_username.is_none() {
-            None
-        } else {
-            Some(MqttCredentials {
-                username: cfg.mqtt_username.unwrap().to_string(),
-                password: cfg.mqtt_password.unwrap().to_string(),
-            })
-        },
+        has_entity_name: cfg.has_entity_name,
+        mqtt_credentials,
         mqtt_clientid: cfg.mqtt_clientid.to_string(),
+        temperature_unit,
+        state_dir: cfg.state_dir,
+        alarm_hysteresis: cfg.alarm_hysteresis,
+        alarm_min_duration_secs: cfg.alarm_min_duration_secs,
+        rate_window_secs: cfg.rate_window_secs,
+        rate_smoothing: cfg.rate_smoothing,
+        pit_channel: cfg.pit_channel,
+        channel_targets,
+        eta_window_secs: cfg.eta_window_secs,
+        stall_temp_min: cfg.stall_temp_min,
+        stall_temp_max: cfg.stall_temp_max,
+        stall_max_rate: cfg.stall_max_rate,
+        stall_window_secs: cfg.stall_window_secs,
+        stall_min_duration_secs: cfg.stall_min_duration_secs,
+        cook_start_temp: cfg.cook_start_temp,
+        cook_end_delay_secs: cfg.cook_end_delay_secs,
+        fire_out_window_secs: cfg.fire_out_window_secs,
+        fire_out_min_fall_rate: cfg.fire_out_min_fall_rate,
+        fire_out_drive_saturation: cfg.fire_out_drive_saturation,
+        lid_open_drop: cfg.lid_open_drop,
+        lid_open_window_secs: cfg.lid_open_window_secs,
+        battery_low_threshold: cfg.battery_low_threshold,
+        stats_windows,
+        stats_entities: cfg.stats_entities,
+        reading_min: cfg.reading_min,
+        reading_max: cfg.reading_max,
+        reading_max_jump: cfg.reading_max_jump,
+        poll_interval_secs: cfg.poll_interval_secs,
+        drive_poll_interval_secs: cfg.drive_poll_interval_secs,
+        idle_poll_interval_secs: cfg.idle_poll_interval_secs,
+        expires_after_secs: (cfg.expires_after_secs != 0).then_some(cfg.expires_after_secs),
+        devicelog_update_interval_secs: cfg.devicelog_update_interval_secs,
+        devices: cfg.devices.unwrap_or_default(),
+    })
+}
+
+/// Loads the config, logging every problem with it and exiting if there are any
+pub async fn load_cfg(args: &ArgMatches) -> Fb2MqttConfig {
+    match build_cfg(args).await {
+        Ok(cfg) => {
+            if cfg.mqtt_credentials.is_none() {
+                info!("no FB2MQTT_MQTT_USERNAME set, mqtt will operate in anonymous mode");
+            }
+            cfg
+        }
+        Err(errors) => {
+            for err in &errors {
+                error!("{}", err);
+            }
+            error!("config is invalid ({} errors)", errors.len());
+            process::exit(1);
+        }
+    }
+}
+
+/// `check-config`: validates the config without connecting to the mqtt broker or the fireboard
+/// api, reporting on stdout and stderr regardless of `RUST_LOG`. As an add-on it still asks the
+/// Supervisor for the mqtt broker, the same as starting the bridge would. Returns the exit code
+pub async fn check_cfg(args: &ArgMatches) -> i32 {
+    match build_cfg(args).await {
+        Ok(cfg) => {
+            println!("{}", serde_json::to_string_pretty(&cfg).unwrap());
+            println!("config ok");
+            0
+        }
+        Err(errors) => {
+            for err in &errors {
+                eprintln!("error: {}", err);
+            }
+            eprintln!("config is invalid ({} errors)", errors.len());
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads a config file the way `--config` does
+    async fn load(name: &str, contents: &str) -> Result<Fb2MqttConfig, Vec<String>> {
+        let path = std::env::temp_dir().join(format!("fb2mqtt-config-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let args = cli_command().get_matches_from([env!("CARGO_PKG_NAME"), "--config", path.to_str().unwrap()]);
+        build_cfg(&args).await
+    }
+
+    const ACCOUNT: &str = r#"
+fireboardaccount_email = "pitmaster@example.com"
+fireboardaccount_password = "secret"
+"#;
+
+    #[tokio::test]
+    async fn valid_config() {
+        let cfg = load("valid", ACCOUNT).await.unwrap();
+        assert_eq!(cfg.accounts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn every_error_is_reported() {
+        let contents = format!(
+            r#"{}
+alarm_hysteresis = -2.0
+stall_max_rate = 0.0
+lid_open_drop = -20.0
+rate_smoothing = 0.0
+battery_low_threshold = 150
+poll_interval_secs = 5
+stall_temp_min = 170.0
+stall_temp_max = 150.0
+"#,
+            ACCOUNT
+        );
+        let errors = load("invalid", &contents).await.unwrap_err();
+        for name in [
+            "FB2MQTT_ALARM_HYSTERESIS",
+            "FB2MQTT_STALL_MAX_RATE",
+            "FB2MQTT_LID_OPEN_DROP",
+            "FB2MQTT_RATE_SMOOTHING",
+            "FB2MQTT_BATTERY_LOW_THRESHOLD",
+            "FB2MQTT_POLL_INTERVAL_SECS",
+            "FB2MQTT_STALL_TEMP_MIN",
+        ] {
+            assert!(
+                errors.iter().any(|err| err.starts_with(name)),
+                "no error for {} in {:?}",
+                name,
+                errors
+            );
+        }
+        assert_eq!(errors.len(), 7, "{:?}", errors);
+    }
+
+    #[tokio::test]
+    async fn missing_account_is_reported_with_the_rest() {
+        let errors = load("no-account", "reading_max_jump = 0.0\n").await.unwrap_err();
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors[0].contains("fireboardaccount_email"));
+        assert!(errors[1].starts_with("FB2MQTT_READING_MAX_JUMP"));
     }
 }
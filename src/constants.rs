@@ -4,8 +4,15 @@ pub const ONLINE: &str = "online";
 pub const OFFLINE: &str = "offline";
 pub const ON: &str = "on";
 pub const OFF: &str = "off";
+/// tells home assistant to reset a number/sensor state to unknown
+pub const PAYLOAD_RESET: &str = "None";
 
-pub const FIREBOARD_DEVICELOG_UPDATE_INTERVAL_MINUTES: i64 = 5;
+/// the fireboard cloud api's rate limit
+pub const FIREBOARD_API_REQUESTS_PER_HOUR: u64 = 200;
+/// how long to wait before retrying an account that couldn't start, doubling after every
+/// failure up to the max
+pub const ACCOUNT_RETRY_MIN_SECS: u64 = 30;
+pub const ACCOUNT_RETRY_MAX_SECS: u64 = 900;
 
 const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const USER_AGENT: &str = concat!("fireboard2mqtt/", CRATE_VERSION);
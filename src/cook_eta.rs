@@ -0,0 +1,236 @@
+//! # Cook ETA
+//!
+//! Estimates when a food probe will reach its target temperature. Meat heats following
+//! Newton's law of heating, `T(t) = A - (A - T0) * e^(-kt)`, where `A` is the temperature
+//! it is heading towards (the pit). That means it slows down as it gets closer to the pit
+//! temperature, which a straight line extrapolation would not account for.
+//!
+//! Taking `ln(A - T)` turns the curve into a straight line with slope `-k`, so once `A` is
+//! known the fit is an ordinary least squares fit. If the pit temperature is measured by a
+//! channel it is used for `A`, otherwise the `A` that fits the readings best is searched for.
+use chrono::{DateTime, Duration, Local};
+use serde::Serialize;
+
+use crate::channel_history::{linear_fit, TempSample};
+
+/// the fewest readings an estimate will be made from
+const MIN_ETA_SAMPLES: usize = 6;
+/// the furthest above the hottest reading the fitted pit temperature is searched for
+const MAX_ASYMPTOTE_SEARCH: f32 = 400.0;
+/// estimates further out than this are not published
+const MAX_ETA_HOURS: i64 = 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EtaMethod {
+    /// the asymptote is the measured pit temperature
+    PitTemperature,
+    /// the asymptote was fitted from the food probe readings alone
+    Fitted,
+    /// the probe has already reached its target
+    Reached,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CookEstimate {
+    pub eta: DateTime<Local>,
+    /// 0 (a guess) to 1 (the readings fit the heating curve perfectly)
+    pub confidence: f32,
+    pub method: EtaMethod,
+    pub target: f32,
+    /// the temperature the probe is heading towards
+    pub asymptote: f32,
+    pub minutes_remaining: f32,
+}
+
+/// Estimates when the readings will reach `target`. Returns `None` when there are not
+/// enough readings or the probe isn't heating up.
+pub fn estimate(
+    samples: &[TempSample],
+    target: f32,
+    pit_temp: Option<f32>,
+    now: DateTime<Local>,
+) -> Option<CookEstimate> {
+    let latest = samples.last()?;
+    if latest.temp >= target {
+        return Some(CookEstimate {
+            eta: latest.time,
+            confidence: 1.0,
+            method: EtaMethod::Reached,
+            target,
+            asymptote: pit_temp.unwrap_or(latest.temp),
+            minutes_remaining: 0.0,
+        });
+    }
+    if samples.len() < MIN_ETA_SAMPLES {
+        return None;
+    }
+
+    let hottest = samples
+        .iter()
+        .map(|sample| sample.temp)
+        .fold(f32::MIN, f32::max);
+    let (fit, method) = match pit_temp {
+        Some(pit_temp) if pit_temp > target && pit_temp > hottest => (
+            fit_with_asymptote(samples, pit_temp)?,
+            EtaMethod::PitTemperature,
+        ),
+        _ => (
+            search_asymptote(samples, hottest.max(target))?,
+            EtaMethod::Fitted,
+        ),
+    };
+
+    let remaining = fit.asymptote - latest.temp;
+    let remaining_at_target = fit.asymptote - target;
+    let minutes_remaining = ((remaining / remaining_at_target).ln() as f64 / fit.k) as f32;
+    if !minutes_remaining.is_finite() || minutes_remaining < 0.0 {
+        return None;
+    }
+    let eta = latest.time + Duration::milliseconds((minutes_remaining * 60_000.0) as i64);
+    if eta - now > Duration::hours(MAX_ETA_HOURS) {
+        return None;
+    }
+
+    // a good fit over a handful of readings is still not worth much
+    let sample_factor = (samples.len() as f32 / 20.0).min(1.0);
+    let method_factor = match method {
+        EtaMethod::PitTemperature => 1.0,
+        _ => 0.8,
+    };
+    Some(CookEstimate {
+        eta,
+        confidence: (fit.r_squared.clamp(0.0, 1.0) * sample_factor * method_factor * 100.0).round()
+            / 100.0,
+        method,
+        target,
+        asymptote: fit.asymptote,
+        minutes_remaining,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HeatingFit {
+    asymptote: f32,
+    /// heating rate constant, per minute
+    k: f64,
+    r_squared: f32,
+}
+
+fn fit_with_asymptote(samples: &[TempSample], asymptote: f32) -> Option<HeatingFit> {
+    let first = samples.first()?.time;
+    let mut points = Vec::with_capacity(samples.len());
+    for sample in samples {
+        let gap = asymptote - sample.temp;
+        if gap <= 0.0 {
+            return None;
+        }
+        let minutes = (sample.time - first).num_milliseconds() as f64 / 60_000.0;
+        points.push((minutes, (gap as f64).ln()));
+    }
+    let (slope, intercept) = linear_fit(&points)?;
+    if slope >= 0.0 {
+        // not heating up
+        return None;
+    }
+
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / points.len() as f64;
+    let (mut residual, mut total) = (0.0, 0.0);
+    for (x, y) in &points {
+        residual += (y - (slope * x + intercept)).powi(2);
+        total += (y - mean_y).powi(2);
+    }
+    let r_squared = if total == 0.0 {
+        0.0
+    } else {
+        1.0 - residual / total
+    };
+    Some(HeatingFit {
+        asymptote,
+        k: -slope,
+        r_squared: r_squared as f32,
+    })
+}
+
+/// Tries asymptotes above `floor` one degree at a time and keeps the best fitting one
+fn search_asymptote(samples: &[TempSample], floor: f32) -> Option<HeatingFit> {
+    (1..=MAX_ASYMPTOTE_SEARCH as u32)
+        .filter_map(|offset| fit_with_asymptote(samples, floor + offset as f32))
+        .max_by(|a, b| a.r_squared.total_cmp(&b.r_squared))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a minute of readings at a time following `T(t) = pit - (pit - start) * e^(-kt)`
+    fn heating(pit: f32, start: f32, k: f32, minutes: i64) -> (Vec<TempSample>, DateTime<Local>) {
+        let begin = Local::now();
+        let samples: Vec<TempSample> = (0..minutes)
+            .map(|minute| TempSample {
+                time: begin + Duration::minutes(minute),
+                temp: pit - (pit - start) * (-k * minute as f32).exp(),
+            })
+            .collect();
+        let now = samples.last().unwrap().time;
+        (samples, now)
+    }
+
+    /// minutes after the last of `minutes` readings until the curve reaches `target`
+    fn minutes_to(pit: f32, start: f32, k: f32, target: f32, minutes: i64) -> f32 {
+        ((pit - start) / (pit - target)).ln() / k - (minutes - 1) as f32
+    }
+
+    #[test]
+    fn newton_fit_from_the_pit_temperature() {
+        let (samples, now) = heating(225.0, 40.0, 0.01, 30);
+        let estimate = estimate(&samples, 203.0, Some(225.0), now).unwrap();
+        assert_eq!(estimate.method, EtaMethod::PitTemperature);
+        assert_eq!(estimate.asymptote, 225.0);
+        // about 184 minutes, extending the current rise in a straight line would say 84
+        let expected = minutes_to(225.0, 40.0, 0.01, 203.0, 30);
+        assert!((estimate.minutes_remaining - expected).abs() < 1.0);
+        assert!(estimate.confidence > 0.95);
+    }
+
+    #[test]
+    fn fitted_asymptote_without_a_pit_channel() {
+        let (samples, now) = heating(225.0, 40.0, 0.01, 30);
+        let estimate = estimate(&samples, 203.0, None, now).unwrap();
+        assert_eq!(estimate.method, EtaMethod::Fitted);
+        assert!((estimate.asymptote - 225.0).abs() <= 1.0);
+        let expected = minutes_to(225.0, 40.0, 0.01, 203.0, 30);
+        assert!((estimate.minutes_remaining - expected).abs() < 5.0);
+        // fitting the asymptote is worth less than measuring it
+        assert!(estimate.confidence <= 0.8);
+    }
+
+    #[test]
+    fn pit_colder_than_the_target_is_ignored() {
+        // the pit probe reading below the target can't be what the food is heading for
+        let (samples, now) = heating(225.0, 40.0, 0.01, 30);
+        let estimate = estimate(&samples, 203.0, Some(190.0), now).unwrap();
+        assert_eq!(estimate.method, EtaMethod::Fitted);
+    }
+
+    #[test]
+    fn target_reached() {
+        let (samples, now) = heating(225.0, 40.0, 0.05, 60);
+        let estimate = estimate(&samples, 203.0, Some(225.0), now).unwrap();
+        assert_eq!(estimate.method, EtaMethod::Reached);
+        assert_eq!(estimate.minutes_remaining, 0.0);
+    }
+
+    #[test]
+    fn too_far_out_to_publish() {
+        // heading for 225 but slowly enough to take days to reach 203
+        let (samples, now) = heating(225.0, 40.0, 0.0001, 30);
+        assert!(estimate(&samples, 203.0, Some(225.0), now).is_none());
+    }
+
+    #[test]
+    fn waits_for_enough_readings() {
+        let (samples, now) = heating(225.0, 40.0, 0.01, MIN_ETA_SAMPLES as i64 - 1);
+        assert!(estimate(&samples, 203.0, Some(225.0), now).is_none());
+    }
+}
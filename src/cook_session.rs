@@ -0,0 +1,329 @@
+//! # Cook Sessions
+//!
+//! Works out when a cook starts and ends without anyone having to start a session in the
+//! Fireboard app. A cook is running while the device is online, at least one probe is
+//! plugged in and the pit is above the start temperature. It ends once that has stopped
+//! being true for a while, so a lid opening or a short wifi dropout doesn't split a cook
+//! in two.
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Local};
+use serde::Serialize;
+
+use crate::channel_stats::{RunningStats, Stats};
+
+#[derive(Debug, Clone)]
+pub struct CookSession {
+    pub started: DateTime<Local>,
+    pit: RunningStats,
+    /// keyed by channel number
+    channels: BTreeMap<usize, RunningStats>,
+    pub lid_openings: u32,
+}
+
+/// The numbers for a cook, published as attributes and with the cook ended event
+#[derive(Debug, Clone, Serialize)]
+pub struct CookSummary {
+    pub started: DateTime<Local>,
+    pub ended: Option<DateTime<Local>>,
+    pub duration_minutes: i64,
+    pub pit_max: Option<f32>,
+    pub pit_mean: Option<f32>,
+    /// keyed by `channel_<n>`
+    pub channel_max: BTreeMap<String, f32>,
+    pub lid_openings: u32,
+}
+
+#[derive(Debug, Clone)]
+pub enum CookTransition {
+    Started(DateTime<Local>),
+    Ended(CookSummary),
+}
+
+/// What a device looks like at one poll
+#[derive(Debug, Clone)]
+pub struct CookReading<'a> {
+    pub online: bool,
+    pub pit_temp: Option<f32>,
+    /// (channel, temp) for every channel with a probe plugged in
+    pub channel_temps: &'a [(usize, f32)],
+}
+
+impl CookReading<'_> {
+    /// without a known pit channel the hottest probe is the best guess at the pit
+    fn pit_temp(&self) -> Option<f32> {
+        self.pit_temp.or_else(|| {
+            self.channel_temps
+                .iter()
+                .map(|(_, temp)| *temp)
+                .reduce(f32::max)
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CookSessionTracker {
+    current: Option<CookSession>,
+    last: Option<CookSummary>,
+    inactive_since: Option<DateTime<Local>>,
+}
+
+impl CookSession {
+    fn new(started: DateTime<Local>) -> CookSession {
+        CookSession {
+            started,
+            pit: RunningStats::default(),
+            channels: BTreeMap::new(),
+            lid_openings: 0,
+        }
+    }
+
+    fn record(&mut self, reading: &CookReading) {
+        if let Some(pit_temp) = reading.pit_temp() {
+            self.pit.push(pit_temp);
+        }
+        for (channel, temp) in reading.channel_temps {
+            self.channels.entry(*channel).or_default().push(*temp);
+        }
+    }
+
+    /// a channel's statistics since the cook started
+    pub fn channel_stats(&self, channel: usize) -> Option<Stats> {
+        self.channels
+            .get(&channel)
+            .and_then(|running| running.stats())
+    }
+
+    fn summary(&self, ended: Option<DateTime<Local>>, now: DateTime<Local>) -> CookSummary {
+        let pit = self.pit.stats();
+        CookSummary {
+            started: self.started,
+            ended,
+            duration_minutes: (ended.unwrap_or(now) - self.started).num_minutes(),
+            pit_max: pit.map(|pit| pit.max),
+            pit_mean: pit.map(|pit| pit.mean),
+            channel_max: self
+                .channels
+                .iter()
+                .filter_map(|(channel, running)| {
+                    running
+                        .stats()
+                        .map(|stats| (format!("channel_{}", channel), stats.max))
+                })
+                .collect(),
+            lid_openings: self.lid_openings,
+        }
+    }
+}
+
+impl CookSessionTracker {
+    /// Feeds a poll into the tracker, returning a transition if a cook started or ended
+    pub fn evaluate(
+        &mut self,
+        reading: &CookReading,
+        start_temp: f32,
+        end_delay: Duration,
+        now: DateTime<Local>,
+    ) -> Option<CookTransition> {
+        let active = reading.online
+            && !reading.channel_temps.is_empty()
+            && reading
+                .pit_temp()
+                .is_some_and(|pit_temp| pit_temp >= start_temp);
+
+        match self.current.as_mut() {
+            None if active => {
+                let mut session = CookSession::new(now);
+                session.record(reading);
+                self.current = Some(session);
+                self.inactive_since = None;
+                Some(CookTransition::Started(now))
+            }
+            None => None,
+            Some(session) => {
+                session.record(reading);
+                if active {
+                    self.inactive_since = None;
+                    return None;
+                }
+                let inactive_since = *self.inactive_since.get_or_insert(now);
+                if now - inactive_since < end_delay {
+                    return None;
+                }
+                // the cook really ended when it went inactive, not when we were sure of it
+                let summary = session.summary(Some(inactive_since), now);
+                self.current = None;
+                self.inactive_since = None;
+                self.last = Some(summary.clone());
+                Some(CookTransition::Ended(summary))
+            }
+        }
+    }
+
+    pub fn current(&self) -> Option<&CookSession> {
+        self.current.as_ref()
+    }
+
+    /// counts a lid opening against the running cook, if there is one
+    pub fn record_lid_opening(&mut self) {
+        if let Some(session) = self.current.as_mut() {
+            session.lid_openings += 1;
+        }
+    }
+
+    /// the running cook's summary so far, or the last cook's once it has ended
+    pub fn summary(&self, now: DateTime<Local>) -> Option<CookSummary> {
+        self.current
+            .as_ref()
+            .map(|session| session.summary(None, now))
+            .or_else(|| self.last.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START_TEMP: f32 = 150.0;
+    const END_DELAY: Duration = Duration::minutes(15);
+
+    fn reading(
+        online: bool,
+        pit_temp: Option<f32>,
+        channel_temps: &[(usize, f32)],
+    ) -> CookReading<'_> {
+        CookReading {
+            online,
+            pit_temp,
+            channel_temps,
+        }
+    }
+
+    #[test]
+    fn starts_once_the_pit_is_hot_with_a_probe_in() {
+        let start = Local::now();
+        let mut tracker = CookSessionTracker::default();
+        let probe = [(2, 60.0)];
+        // heating up
+        assert!(tracker
+            .evaluate(
+                &reading(true, Some(120.0), &probe),
+                START_TEMP,
+                END_DELAY,
+                start
+            )
+            .is_none());
+        // hot, but nothing to cook
+        let now = start + Duration::minutes(5);
+        assert!(tracker
+            .evaluate(&reading(true, Some(225.0), &[]), START_TEMP, END_DELAY, now)
+            .is_none());
+        let now = start + Duration::minutes(10);
+        assert!(matches!(
+            tracker.evaluate(&reading(true, Some(225.0), &probe), START_TEMP, END_DELAY, now),
+            Some(CookTransition::Started(started)) if started == now
+        ));
+        assert_eq!(tracker.current().unwrap().started, now);
+    }
+
+    #[test]
+    fn hottest_probe_stands_in_for_the_pit() {
+        let mut tracker = CookSessionTracker::default();
+        let probes = [(1, 230.0), (2, 60.0)];
+        assert!(matches!(
+            tracker.evaluate(
+                &reading(true, None, &probes),
+                START_TEMP,
+                END_DELAY,
+                Local::now()
+            ),
+            Some(CookTransition::Started(_))
+        ));
+    }
+
+    #[test]
+    fn survives_a_brief_dropout() {
+        let start = Local::now();
+        let mut tracker = CookSessionTracker::default();
+        let probe = [(2, 150.0)];
+        tracker.evaluate(
+            &reading(true, Some(225.0), &probe),
+            START_TEMP,
+            END_DELAY,
+            start,
+        );
+        // offline for 10 minutes, then back
+        for minute in [1, 5, 10] {
+            let now = start + Duration::minutes(minute);
+            assert!(tracker
+                .evaluate(&reading(false, None, &[]), START_TEMP, END_DELAY, now)
+                .is_none());
+        }
+        let now = start + Duration::minutes(11);
+        assert!(tracker
+            .evaluate(
+                &reading(true, Some(225.0), &probe),
+                START_TEMP,
+                END_DELAY,
+                now
+            )
+            .is_none());
+        // the dropout doesn't count towards a later one
+        let now = start + Duration::minutes(20);
+        assert!(tracker
+            .evaluate(&reading(false, None, &[]), START_TEMP, END_DELAY, now)
+            .is_none());
+        let now = start + Duration::minutes(30);
+        assert!(tracker
+            .evaluate(&reading(false, None, &[]), START_TEMP, END_DELAY, now)
+            .is_none());
+        assert_eq!(tracker.current().unwrap().started, start);
+    }
+
+    #[test]
+    fn ends_after_the_delay_from_when_it_went_inactive() {
+        let start = Local::now();
+        let mut tracker = CookSessionTracker::default();
+        tracker.evaluate(
+            &reading(true, Some(225.0), &[(2, 150.0)]),
+            START_TEMP,
+            END_DELAY,
+            start,
+        );
+        tracker.record_lid_opening();
+        let now = start + Duration::minutes(60);
+        tracker.evaluate(
+            &reading(true, Some(250.0), &[(2, 203.0)]),
+            START_TEMP,
+            END_DELAY,
+            now,
+        );
+
+        // probe pulled, the fire dies down
+        let ended = start + Duration::minutes(90);
+        assert!(tracker
+            .evaluate(
+                &reading(true, Some(200.0), &[]),
+                START_TEMP,
+                END_DELAY,
+                ended
+            )
+            .is_none());
+        let now = ended + END_DELAY;
+        let Some(CookTransition::Ended(summary)) =
+            tracker.evaluate(&reading(true, Some(140.0), &[]), START_TEMP, END_DELAY, now)
+        else {
+            panic!("expected the cook to end");
+        };
+        assert_eq!(summary.started, start);
+        assert_eq!(summary.ended, Some(ended));
+        assert_eq!(summary.duration_minutes, 90);
+        assert_eq!(summary.pit_max, Some(250.0));
+        assert_eq!(summary.channel_max["channel_2"], 203.0);
+        assert_eq!(summary.lid_openings, 1);
+
+        assert!(tracker.current().is_none());
+        // the last cook's summary stays around until the next one
+        assert_eq!(tracker.summary(now).unwrap().ended, Some(ended));
+    }
+}
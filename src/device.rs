@@ -1,45 +1,147 @@
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
+use strum::Display;
 
 use crate::constants::{OFFLINE, ONLINE};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct FireboardMqttChannel {
-    pub availability: String,
-    pub state: f32,
+/// see https://developers.home-assistant.io/docs/core/entity/#generic-properties
+#[derive(Debug, Serialize, Deserialize, Display, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum EntityCategory {
+    Config,
+    Diagnostic,
 }
 
+/// The discovery fields shared by every entity type. Each discovery struct flattens one
+/// of these, build it with [`MQTTDiscoveryEntity::builder`].
 #[derive(Debug, Serialize, Deserialize)]
-pub struct MQTTDiscoverySensor {
+pub struct MQTTDiscoveryEntity {
     pub unique_id: String,
     pub object_id: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// when set, home assistant shows the entity as its device's name followed by `name`,
+    /// left unset the entity is shown as just `name`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_entity_name: Option<bool>,
     pub availability: Vec<MQTTDiscoveryAvailabilityEntry>,
+    /// see https://www.home-assistant.io/integrations/sensor.mqtt/#availability_mode
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub device_class: Option<String>,
+    pub availability_mode: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub options: Option<Vec<String>>,
+    pub entity_category: Option<EntityCategory>,
     pub enabled_by_default: bool,
     pub encoding: String,
+    pub qos: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<MQTTDiscoveryDevice>,
+}
+
+impl MQTTDiscoveryEntity {
+    pub fn builder(unique_id: impl Into<String>) -> MQTTDiscoveryEntityBuilder {
+        let unique_id = unique_id.into();
+        MQTTDiscoveryEntityBuilder {
+            entity: MQTTDiscoveryEntity {
+                object_id: unique_id.clone(),
+                unique_id,
+                ..MQTTDiscoveryEntity::default()
+            },
+        }
+    }
+}
+
+impl Default for MQTTDiscoveryEntity {
+    fn default() -> Self {
+        MQTTDiscoveryEntity {
+            unique_id: "".to_string(),
+            object_id: "".to_string(),
+            name: None,
+            has_entity_name: None,
+            availability: vec![],
+            availability_mode: None,
+            entity_category: None,
+            enabled_by_default: true,
+            encoding: "utf-8".to_string(),
+            qos: 0,
+            icon: None,
+            device: None,
+        }
+    }
+}
+
+pub struct MQTTDiscoveryEntityBuilder {
+    entity: MQTTDiscoveryEntity,
+}
+
+impl MQTTDiscoveryEntityBuilder {
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.entity.name = Some(name.into());
+        self
+    }
+
+    pub fn has_entity_name(mut self, has_entity_name: bool) -> Self {
+        self.entity.has_entity_name = has_entity_name.then_some(true);
+        self
+    }
+
+    /// The entity is only available while every one of these topics is `online`
+    pub fn availability(mut self, topics: Vec<String>) -> Self {
+        if topics.len() > 1 {
+            self.entity.availability_mode = Some("all".to_string());
+        }
+        self.entity.availability = topics
+            .into_iter()
+            .map(MQTTDiscoveryAvailabilityEntry::from)
+            .collect();
+        self
+    }
+
+    pub fn entity_category(mut self, entity_category: EntityCategory) -> Self {
+        self.entity.entity_category = Some(entity_category);
+        self
+    }
+
+    pub fn icon(mut self, icon: impl Into<String>) -> Self {
+        self.entity.icon = Some(icon.into());
+        self
+    }
+
+    pub fn device(mut self, device: Option<MQTTDiscoveryDevice>) -> Self {
+        self.entity.device = device;
+        self
+    }
+
+    pub fn build(self) -> MQTTDiscoveryEntity {
+        self.entity
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MQTTDiscoverySensor {
+    #[serde(flatten)]
+    pub entity: MQTTDiscoveryEntity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_class: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub suggested_display_precision: Option<u16>,
-    pub qos: u8,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub state_class: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub json_attributes_topic: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub icon: Option<String>,
     /// see https://www.home-assistant.io/integrations/sensor.mqtt/#state_topic
     pub state_topic: String,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub unit_of_measurement: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub suggested_unit_of_measurement: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub device: Option<MQTTDiscoveryDevice>,
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub expires_after: Option<u32>,
 }
 
@@ -53,23 +155,16 @@ impl From<MQTTDiscoverySensor> for Bytes {
 impl Default for MQTTDiscoverySensor {
     fn default() -> Self {
         MQTTDiscoverySensor {
-            unique_id: "".to_string(),
-            object_id: "".to_string(),
-            name: None,
-            availability: vec![],
+            entity: MQTTDiscoveryEntity::default(),
             device_class: None,
-            enabled_by_default: true,
-            encoding: "utf-8".to_string(),
             suggested_display_precision: None,
             options: None,
-            qos: 0,
             state_class: Some("measurement".to_string()),
             json_attributes_topic: None,
-            icon: None,
             state_topic: "".to_string(),
+            value_template: None,
             unit_of_measurement: None,
             suggested_unit_of_measurement: None,
-            device: None,
             expires_after: None,
         }
     }
@@ -77,37 +172,23 @@ impl Default for MQTTDiscoverySensor {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MQTTDiscoveryBinarySensor {
-    pub unique_id: String,
-    pub object_id: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub name: Option<String>,
-    pub availability: Vec<MQTTDiscoveryAvailabilityEntry>,
+    #[serde(flatten)]
+    pub entity: MQTTDiscoveryEntity,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub device_class: Option<String>,
-    pub enabled_by_default: bool,
-    pub encoding: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub suggested_display_precision: Option<u16>,
-    pub qos: u8,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub json_attributes_topic: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub icon: Option<String>,
-    /// see https://www.home-assistant.io/integrations/sensor.mqtt/#state_topic
+    /// see https://www.home-assistant.io/integrations/binary_sensor.mqtt/#state_topic
     pub state_topic: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub payload_on: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub payload_off: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub device: Option<MQTTDiscoveryDevice>,
 }
 
 impl From<MQTTDiscoveryBinarySensor> for Bytes {
     fn from(sensor: MQTTDiscoveryBinarySensor) -> Bytes {
-        // let mut sensor = json!(self);
         let json = serde_json::to_string(&sensor).unwrap();
-        // sensor.as_object_mut().unwrap().insert("device".to_string(), json!({}));
         Bytes::from(json)
     }
 }
@@ -115,25 +196,225 @@ impl From<MQTTDiscoveryBinarySensor> for Bytes {
 impl Default for MQTTDiscoveryBinarySensor {
     fn default() -> Self {
         MQTTDiscoveryBinarySensor {
-            unique_id: "".to_string(),
-            object_id: "".to_string(),
-            name: None,
-            availability: vec![],
+            entity: MQTTDiscoveryEntity::default(),
             device_class: None,
-            enabled_by_default: true,
-            encoding: "utf-8".to_string(),
-            suggested_display_precision: None,
-            qos: 0,
             json_attributes_topic: None,
-            icon: None,
             state_topic: "".to_string(),
             payload_on: None,
             payload_off: None,
-            device: None,
         }
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MQTTDiscoveryNumber {
+    #[serde(flatten)]
+    pub entity: MQTTDiscoveryEntity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_class: Option<String>,
+    /// see https://www.home-assistant.io/integrations/number.mqtt/#command_topic
+    pub command_topic: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_topic: Option<String>,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+    /// one of `auto`, `box` or `slider`
+    pub mode: String,
+    pub optimistic: bool,
+    pub retain: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit_of_measurement: Option<String>,
+}
+
+impl From<MQTTDiscoveryNumber> for Bytes {
+    fn from(number: MQTTDiscoveryNumber) -> Bytes {
+        let json = serde_json::to_string(&number).unwrap();
+        Bytes::from(json)
+    }
+}
+
+impl Default for MQTTDiscoveryNumber {
+    fn default() -> Self {
+        MQTTDiscoveryNumber {
+            entity: MQTTDiscoveryEntity::default(),
+            device_class: None,
+            command_topic: "".to_string(),
+            state_topic: None,
+            min: 1.0,
+            max: 100.0,
+            step: 1.0,
+            mode: "box".to_string(),
+            optimistic: false,
+            retain: false,
+            unit_of_measurement: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MQTTDiscoverySelect {
+    #[serde(flatten)]
+    pub entity: MQTTDiscoveryEntity,
+    /// see https://www.home-assistant.io/integrations/select.mqtt/#command_topic
+    pub command_topic: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_topic: Option<String>,
+    pub options: Vec<String>,
+    pub optimistic: bool,
+    pub retain: bool,
+}
+
+impl From<MQTTDiscoverySelect> for Bytes {
+    fn from(select: MQTTDiscoverySelect) -> Bytes {
+        let json = serde_json::to_string(&select).unwrap();
+        Bytes::from(json)
+    }
+}
+
+impl Default for MQTTDiscoverySelect {
+    fn default() -> Self {
+        MQTTDiscoverySelect {
+            entity: MQTTDiscoveryEntity::default(),
+            command_topic: "".to_string(),
+            state_topic: None,
+            options: vec![],
+            optimistic: false,
+            retain: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MQTTDiscoveryClimate {
+    #[serde(flatten)]
+    pub entity: MQTTDiscoveryEntity,
+    /// see https://www.home-assistant.io/integrations/climate.mqtt/#current_temperature_topic
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_temperature_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature_state_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature_command_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode_state_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode_command_topic: Option<String>,
+    pub modes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action_topic: Option<String>,
+    pub min_temp: f32,
+    pub max_temp: f32,
+    pub temp_step: f32,
+    pub precision: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature_unit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_attributes_topic: Option<String>,
+    pub optimistic: bool,
+    pub retain: bool,
+}
+
+impl From<MQTTDiscoveryClimate> for Bytes {
+    fn from(climate: MQTTDiscoveryClimate) -> Bytes {
+        let json = serde_json::to_string(&climate).unwrap();
+        Bytes::from(json)
+    }
+}
+
+impl Default for MQTTDiscoveryClimate {
+    fn default() -> Self {
+        MQTTDiscoveryClimate {
+            entity: MQTTDiscoveryEntity::default(),
+            current_temperature_topic: None,
+            temperature_state_topic: None,
+            temperature_command_topic: None,
+            mode_state_topic: None,
+            mode_command_topic: None,
+            modes: vec![],
+            action_topic: None,
+            min_temp: 7.0,
+            max_temp: 35.0,
+            temp_step: 1.0,
+            precision: 0.1,
+            temperature_unit: None,
+            json_attributes_topic: None,
+            optimistic: false,
+            retain: false,
+        }
+    }
+}
+
+/// see https://www.home-assistant.io/integrations/event.mqtt/
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MQTTDiscoveryEvent {
+    #[serde(flatten)]
+    pub entity: MQTTDiscoveryEntity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_class: Option<String>,
+    /// the json payloads published here must have an `event_type` from `event_types`
+    pub state_topic: String,
+    pub event_types: Vec<String>,
+}
+
+impl From<MQTTDiscoveryEvent> for Bytes {
+    fn from(event: MQTTDiscoveryEvent) -> Bytes {
+        let json = serde_json::to_string(&event).unwrap();
+        Bytes::from(json)
+    }
+}
+
+impl Default for MQTTDiscoveryEvent {
+    fn default() -> Self {
+        MQTTDiscoveryEvent {
+            entity: MQTTDiscoveryEntity::default(),
+            device_class: None,
+            state_topic: "".to_string(),
+            event_types: vec![],
+        }
+    }
+}
+
+/// see https://www.home-assistant.io/integrations/device_trigger.mqtt/
+/// Device triggers aren't entities, so they don't flatten an [`MQTTDiscoveryEntity`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MQTTDiscoveryDeviceTrigger {
+    pub automation_type: String,
+    pub topic: String,
+    #[serde(rename = "type")]
+    pub trigger_type: String,
+    pub subtype: String,
+    /// the trigger only fires when the (templated) message matches this
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_template: Option<String>,
+    pub qos: u8,
+    pub device: MQTTDiscoveryDevice,
+}
+
+impl Default for MQTTDiscoveryDeviceTrigger {
+    fn default() -> Self {
+        MQTTDiscoveryDeviceTrigger {
+            automation_type: "trigger".to_string(),
+            topic: "".to_string(),
+            trigger_type: "".to_string(),
+            subtype: "".to_string(),
+            payload: None,
+            value_template: None,
+            qos: 0,
+            device: MQTTDiscoveryDevice::default(),
+        }
+    }
+}
+
+impl From<MQTTDiscoveryDeviceTrigger> for Bytes {
+    fn from(trigger: MQTTDiscoveryDeviceTrigger) -> Bytes {
+        let json = serde_json::to_string(&trigger).unwrap();
+        Bytes::from(json)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MQTTDiscoveryAvailabilityEntry {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -175,6 +456,8 @@ pub struct MQTTDiscoveryDevice {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub serial_number: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggested_area: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub sw_version: Option<String>,
 }
 
@@ -0,0 +1,97 @@
+//! # Device Events
+//!
+//! One-off things that happen to a device (a probe entering a stall, a probe being
+//! unplugged, etc). These are published, unretained, as json to the device's event topic so
+//! automations can react to them without having to watch for state changes. Some of them
+//! are also announced to home assistant as device triggers.
+use bytes::Bytes;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use strum::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum DeviceEventType {
+    StallStart,
+    StallEnd,
+    ProbeConnected,
+    ProbeDisconnected,
+    DeviceOnline,
+    DeviceOffline,
+    CookStarted,
+    CookEnded,
+    FireOut,
+    FireRecovered,
+}
+
+/// the events published to the cook session event entity
+pub const COOK_EVENTS: [DeviceEventType; 2] =
+    [DeviceEventType::CookStarted, DeviceEventType::CookEnded];
+
+/// the events published as home assistant device triggers for every channel
+pub const CHANNEL_TRIGGER_EVENTS: [DeviceEventType; 2] = [
+    DeviceEventType::ProbeConnected,
+    DeviceEventType::ProbeDisconnected,
+];
+
+/// the events published as home assistant device triggers for the device itself
+pub const DEVICE_TRIGGER_EVENTS: [DeviceEventType; 2] = [
+    DeviceEventType::DeviceOnline,
+    DeviceEventType::DeviceOffline,
+];
+
+/// matches a channel trigger's payload against a channel event, e.g. `probe_connected:1`
+pub const CHANNEL_TRIGGER_VALUE_TEMPLATE: &str =
+    "{{ value_json.event_type }}:{{ value_json.channel }}";
+
+/// matches a device trigger's payload against a device event, e.g. `device_online`
+pub const DEVICE_TRIGGER_VALUE_TEMPLATE: &str = "{{ value_json.event_type }}";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceEvent {
+    pub event_type: DeviceEventType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<usize>,
+    pub timestamp: DateTime<Local>,
+    /// anything specific to the event type
+    #[serde(flatten)]
+    pub details: Map<String, Value>,
+}
+
+impl DeviceEvent {
+    pub fn new(event_type: DeviceEventType, timestamp: DateTime<Local>) -> DeviceEvent {
+        DeviceEvent {
+            event_type,
+            channel: None,
+            timestamp,
+            details: Map::new(),
+        }
+    }
+
+    pub fn channel(mut self, channel: usize) -> DeviceEvent {
+        self.channel = Some(channel);
+        self
+    }
+
+    pub fn detail(mut self, key: &str, value: impl Into<Value>) -> DeviceEvent {
+        self.details.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// adds every field of `details` (which has to serialize to a json object)
+    pub fn details(mut self, details: &impl Serialize) -> DeviceEvent {
+        if let Ok(Value::Object(details)) = serde_json::to_value(details) {
+            self.details.extend(details);
+        }
+        self
+    }
+}
+
+impl From<DeviceEvent> for Bytes {
+    fn from(event: DeviceEvent) -> Bytes {
+        let json = serde_json::to_string(&event).unwrap();
+        Bytes::from(json)
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use chrono::Duration;
+
+use crate::alarm::ChannelAlarm;
+use crate::battery::BatteryModel;
+use crate::channel_history::ChannelHistory;
+use crate::cook_session::CookSessionTracker;
+use crate::fire_out::FireOutDetector;
+use crate::fireboard_api::DegreeType;
+use crate::lid_open::LidOpenDetector;
+use crate::reading_filter::ReadingFilter;
+use crate::stall::StallDetector;
+
+/// What the watcher remembers about a device between polls
+#[derive(Debug, Clone)]
+pub struct DeviceState {
+    pub uuid: String,
+    /// the unit the device reports temperatures in
+    pub degreetype: DegreeType,
+    /// the unit temperatures for the device are published in
+    pub unit: DegreeType,
+    /// keyed by channel number
+    pub channels: HashMap<usize, ChannelState>,
+    /// the channel a drive is controlling the pit temperature from, if there is a drive
+    pub drive_tied_channel: Option<usize>,
+    /// whether the device was online at the last poll, `None` until the first poll
+    pub online: Option<bool>,
+    pub cook: CookSessionTracker,
+    pub fire_out: FireOutDetector,
+    pub lid: LidOpenDetector,
+    pub battery: BatteryModel,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelState {
+    pub history: ChannelHistory,
+    pub high_alarm: ChannelAlarm,
+    pub low_alarm: ChannelAlarm,
+    /// smoothed rate of change, in degrees per minute
+    pub rate_per_minute: Option<f32>,
+    pub stall: StallDetector,
+    /// whether a probe was plugged in at the last poll, `None` until the first poll
+    pub connected: Option<bool>,
+    pub filter: ReadingFilter,
+}
+
+impl ChannelState {
+    pub fn new(history_retention: Duration) -> ChannelState {
+        ChannelState {
+            history: ChannelHistory::new(history_retention),
+            high_alarm: ChannelAlarm::default(),
+            low_alarm: ChannelAlarm::default(),
+            rate_per_minute: None,
+            stall: StallDetector::default(),
+            connected: None,
+            filter: ReadingFilter::default(),
+        }
+    }
+}
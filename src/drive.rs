@@ -14,4 +14,4 @@ impl From<DriveAttributes> for Bytes {
         let json = serde_json::to_string(&drive_attributes).unwrap();
         Bytes::from(json)
     }
-}
\ No newline at end of file
+}
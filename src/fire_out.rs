@@ -0,0 +1,216 @@
+//! # Fire Out Detection
+//!
+//! Spots the fire going out (or running out of fuel) during a cook. With a Drive the tell
+//! is the blower running flat out while the pit keeps cooling anyway. Without one all we
+//! have is the pit channel, so a pit that keeps falling for the whole window is enough.
+use chrono::{DateTime, Duration, Local};
+
+/// Everything needed to decide whether the fire is out, in the device's output unit
+#[derive(Debug, Clone, Copy)]
+pub struct FireOutCriteria {
+    /// how long the pit has to keep falling
+    pub window: Duration,
+    /// the pit has to be falling at least this fast, in degrees per hour
+    pub min_fall_rate_per_hour: f32,
+    /// the drive percentage (0-1) at which the blower is considered flat out
+    pub drive_saturation: f32,
+}
+
+/// What the pit and drive look like at one poll
+#[derive(Debug, Clone, Copy)]
+pub struct FireOutReading {
+    pub pit_temp: f32,
+    pub pit_rate_per_hour: Option<f32>,
+    /// the drive's duty, only when there is a drive
+    pub driveper: Option<f32>,
+    /// the drive's setpoint, only when the drive is controlling to one
+    pub setpoint: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FireOutTransition {
+    Out { since: DateTime<Local> },
+    Recovered { since: DateTime<Local> },
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct FireOutDetector {
+    out_since: Option<DateTime<Local>>,
+    falling_since: Option<DateTime<Local>>,
+}
+
+impl FireOutReading {
+    fn falling(&self, criteria: &FireOutCriteria) -> bool {
+        let falling = self
+            .pit_rate_per_hour
+            .is_some_and(|rate| rate <= -criteria.min_fall_rate_per_hour);
+        let drive_saturated = self
+            .driveper
+            .is_none_or(|driveper| driveper >= criteria.drive_saturation);
+        let below_setpoint = self
+            .setpoint
+            .is_none_or(|setpoint| self.pit_temp < setpoint);
+        falling && drive_saturated && below_setpoint
+    }
+}
+
+impl FireOutDetector {
+    /// Feeds a poll into the detector, returning a transition if the fire went out or
+    /// came back
+    pub fn evaluate(
+        &mut self,
+        reading: &FireOutReading,
+        criteria: &FireOutCriteria,
+        now: DateTime<Local>,
+    ) -> Option<FireOutTransition> {
+        if let Some(since) = self.out_since {
+            // the fire is back once the pit stops falling
+            if reading.pit_rate_per_hour.is_some_and(|rate| rate > 0.0) {
+                self.reset();
+                return Some(FireOutTransition::Recovered { since });
+            }
+            return None;
+        }
+
+        if !reading.falling(criteria) {
+            self.falling_since = None;
+            return None;
+        }
+
+        let falling_since = *self.falling_since.get_or_insert(now);
+        if now - falling_since >= criteria.window {
+            self.out_since = Some(falling_since);
+            return Some(FireOutTransition::Out {
+                since: falling_since,
+            });
+        }
+        None
+    }
+
+    pub fn out(&self) -> bool {
+        self.out_since.is_some()
+    }
+
+    pub fn reset(&mut self) {
+        self.out_since = None;
+        self.falling_since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CRITERIA: FireOutCriteria = FireOutCriteria {
+        window: Duration::minutes(20),
+        min_fall_rate_per_hour: 15.0,
+        drive_saturation: 0.95,
+    };
+
+    /// A pit at 200 falling at `rate_per_hour`, with the drive at `driveper` trying to get
+    /// back to 225
+    fn drive_reading(rate_per_hour: f32, driveper: f32) -> FireOutReading {
+        FireOutReading {
+            pit_temp: 200.0,
+            pit_rate_per_hour: Some(rate_per_hour),
+            driveper: Some(driveper),
+            setpoint: Some(225.0),
+        }
+    }
+
+    /// Feeds the same reading every 5 minutes for an hour, returning the transitions
+    fn run(reading: FireOutReading) -> Vec<FireOutTransition> {
+        let start = Local::now();
+        let mut detector = FireOutDetector::default();
+        (0..12)
+            .filter_map(|i| {
+                detector.evaluate(&reading, &CRITERIA, start + Duration::minutes(i * 5))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn falling_with_the_blower_flat_out() {
+        let start = Local::now();
+        let mut detector = FireOutDetector::default();
+        let reading = drive_reading(-30.0, 1.0);
+        for minute in [0, 5, 10, 15] {
+            assert_eq!(
+                detector.evaluate(&reading, &CRITERIA, start + Duration::minutes(minute)),
+                None
+            );
+        }
+        assert_eq!(
+            detector.evaluate(&reading, &CRITERIA, start + Duration::minutes(20)),
+            Some(FireOutTransition::Out { since: start })
+        );
+        assert!(detector.out());
+
+        // still falling, no repeat
+        let now = start + Duration::minutes(25);
+        assert_eq!(detector.evaluate(&reading, &CRITERIA, now), None);
+        // someone added fuel
+        let now = start + Duration::minutes(40);
+        assert_eq!(
+            detector.evaluate(&drive_reading(20.0, 1.0), &CRITERIA, now),
+            Some(FireOutTransition::Recovered { since: start })
+        );
+        assert!(!detector.out());
+    }
+
+    #[test]
+    fn slow_drift_is_not_the_fire_out() {
+        assert!(run(drive_reading(-5.0, 1.0)).is_empty());
+    }
+
+    #[test]
+    fn not_while_the_blower_has_headroom() {
+        // the drive hasn't run out of ways to heat the pit
+        assert!(run(drive_reading(-30.0, 0.6)).is_empty());
+    }
+
+    #[test]
+    fn not_while_cooling_to_a_lower_setpoint() {
+        let reading = FireOutReading {
+            setpoint: Some(180.0),
+            ..drive_reading(-30.0, 1.0)
+        };
+        assert!(run(reading).is_empty());
+    }
+
+    #[test]
+    fn pit_falling_without_a_drive() {
+        let reading = FireOutReading {
+            pit_temp: 200.0,
+            pit_rate_per_hour: Some(-30.0),
+            driveper: None,
+            setpoint: None,
+        };
+        assert_eq!(run(reading).len(), 1);
+    }
+
+    #[test]
+    fn interrupted_fall_starts_over() {
+        let start = Local::now();
+        let mut detector = FireOutDetector::default();
+        let falling = drive_reading(-30.0, 1.0);
+        detector.evaluate(&falling, &CRITERIA, start);
+        detector.evaluate(&falling, &CRITERIA, start + Duration::minutes(10));
+        // the blower catches up for a moment
+        detector.evaluate(
+            &drive_reading(-30.0, 0.5),
+            &CRITERIA,
+            start + Duration::minutes(15),
+        );
+        let restarted = start + Duration::minutes(20);
+        assert_eq!(detector.evaluate(&falling, &CRITERIA, restarted), None);
+        assert_eq!(
+            detector.evaluate(&falling, &CRITERIA, start + Duration::minutes(35)),
+            None
+        );
+        assert_eq!(
+            detector.evaluate(&falling, &CRITERIA, restarted + CRITERIA.window),
+            Some(FireOutTransition::Out { since: restarted })
+        );
+    }
+}
@@ -3,17 +3,18 @@ use chrono::{DateTime, Local};
 use log::{debug, error};
 use reqwest::{
     header::{HeaderMap, HeaderValue},
-    Url,
+    StatusCode, Url,
 };
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::{json, Value};
-use strum::Display;
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
 use std::sync::Arc;
 extern crate serde_json;
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
 use crate::{
     constants::USER_AGENT,
+    temperature::Temperature,
     utils::deserialize_empty_object,
 };
 
@@ -28,11 +29,6 @@ pub struct FireboardCloudApiAuthResponse {
 }
 
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct FireboardDeviceList {
-    pub devices: Vec<FireboardApiDevice>,
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FireboardApiDevice {
     pub id: usize,
@@ -50,6 +46,15 @@ pub struct FireboardApiDevice {
 }
 
 
+impl FireboardApiDevice {
+    /// a channel's last reading, the api reports readings in the device's unit
+    pub fn channel_temperature(&self, channel: &FireboardDeviceChannel) -> Option<Temperature> {
+        channel
+            .last_templog
+            .map(|templog| Temperature::new(templog.temp, self.degreetype))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FireboardDeviceLog {
     pub date: DateTime<Local>,
@@ -83,6 +88,13 @@ pub struct FireboardRealtimeDrivelog {
     pub driveper: f32,
 }
 
+impl FireboardRealtimeDrivelog {
+    /// the setpoint is reported in the unit of the device the drive is plugged into
+    pub fn setpoint_temperature(&self, degreetype: DegreeType) -> Temperature {
+        Temperature::new(self.setpoint, degreetype)
+    }
+}
+
 fn drivemode_from_string<'de, D>(deserializer: D) -> Result<DriveModeType, D::Error>
 where
     D: Deserializer<'de>,
@@ -92,16 +104,69 @@ where
 }
 
 
-#[derive(Serialize_repr, Deserialize_repr, PartialEq, Debug, Copy, Clone, Display)]
+#[derive(Serialize_repr, Deserialize_repr, PartialEq, Debug, Copy, Clone, Display, EnumString)]
+#[strum(ascii_case_insensitive)]
 #[repr(u8)]
 pub enum DegreeType {
-    #[strum(to_string = "°C")]
+    #[strum(to_string = "°C", serialize = "C", serialize = "celsius")]
     Celcius = 1,
-    #[strum(to_string = "°F")]
+    #[strum(to_string = "°F", serialize = "F", serialize = "fahrenheit")]
     Fahrenheit = 2,
 }
 
-#[derive(Serialize_repr, Deserialize_repr, PartialEq, Debug, Copy, Clone, Display)]
+impl DegreeType {
+    /// the lowest setpoint a Fireboard Drive will accept in this unit
+    pub fn drive_setpoint_min(&self) -> f32 {
+        match self {
+            DegreeType::Celcius => 38.0,
+            DegreeType::Fahrenheit => 100.0,
+        }
+    }
+
+    /// the highest setpoint a Fireboard Drive will accept in this unit
+    pub fn drive_setpoint_max(&self) -> f32 {
+        match self {
+            DegreeType::Celcius => 343.0,
+            DegreeType::Fahrenheit => 650.0,
+        }
+    }
+
+    /// the lowest temperature a Fireboard probe can read in this unit
+    pub fn probe_min(&self) -> f32 {
+        match self {
+            DegreeType::Celcius => -40.0,
+            DegreeType::Fahrenheit => -40.0,
+        }
+    }
+
+    /// the highest temperature a Fireboard probe can read in this unit
+    pub fn probe_max(&self) -> f32 {
+        match self {
+            DegreeType::Celcius => 538.0,
+            DegreeType::Fahrenheit => 1000.0,
+        }
+    }
+
+    pub fn drive_setpoint_step(&self) -> f32 {
+        match self {
+            DegreeType::Celcius => 0.5,
+            DegreeType::Fahrenheit => 1.0,
+        }
+    }
+
+    /// see https://www.home-assistant.io/integrations/climate.mqtt/#temperature_unit
+    pub fn hvac_temperature_unit(&self) -> &'static str {
+        match self {
+            DegreeType::Celcius => "C",
+            DegreeType::Fahrenheit => "F",
+        }
+    }
+}
+
+#[derive(
+    Serialize_repr, Deserialize_repr, PartialEq, Debug, Copy, Clone, Display, EnumString, EnumIter,
+)]
+#[strum(ascii_case_insensitive)]
 #[repr(u8)]
 pub enum DriveModeType {
     #[strum(to_string = "off")]
@@ -112,6 +177,32 @@ pub enum DriveModeType {
     Auto = 2,
 }
 
+/// The Drive behaves like a thermostat, so its modes are mapped onto home assistant
+/// hvac modes for the climate entity
+impl DriveModeType {
+    pub fn hvac_mode(&self) -> &'static str {
+        match self {
+            DriveModeType::Off => "off",
+            DriveModeType::Manual => "fan_only",
+            DriveModeType::Auto => "heat",
+        }
+    }
+
+    pub fn from_hvac_mode(hvac_mode: &str) -> Option<DriveModeType> {
+        DriveModeType::iter().find(|mode| mode.hvac_mode() == hvac_mode)
+    }
+
+    /// see https://www.home-assistant.io/integrations/climate.mqtt/#action_topic
+    pub fn hvac_action(&self, driveper: f32) -> &'static str {
+        match self {
+            DriveModeType::Off => "off",
+            _ if driveper <= 0.0 => "idle",
+            DriveModeType::Manual => "fan",
+            DriveModeType::Auto => "heating",
+        }
+    }
+}
+
 impl From<String> for DriveModeType {
     fn from(s: String) -> Self {
         match s.to_lowercase().as_str() {
@@ -126,12 +217,12 @@ impl From<String> for DriveModeType {
 pub struct FireboardApiClient {
     api_base: url::Url,
     client: Arc<reqwest::Client>,
+    token: String,
 }
 
 impl FireboardApiClient {
-    pub async fn new(user_email: String, user_password: String) -> Result<FireboardApiClient> {
-        let api_base = Url::parse("https://fireboard.io/api/")?;
-
+    /// Logs in with the account's email and password for a new token
+    pub async fn login(user_email: String, user_password: String) -> Result<FireboardApiClient> {
         let credentials = FireboardCloudApiAuthRequest {
             username: user_email.to_string(),
             password: user_password.to_string(),
@@ -146,8 +237,6 @@ impl FireboardApiClient {
             .send()
             .await;
         if let Ok(auth_response) = auth_result {
-            // let auth_response = auth_result.unwrap();
-
             let auth = match auth_response.error_for_status() {
                 Ok(r) => r.json::<FireboardCloudApiAuthResponse>().await?,
                 Err(e) => {
@@ -156,22 +245,7 @@ impl FireboardApiClient {
                 }
             };
 
-            let mut headers = HeaderMap::new();
-            headers.insert("Content-Type", HeaderValue::from_static("application/json"));
-            headers.insert("User-Agent", HeaderValue::from_static(USER_AGENT));
-            headers.insert(
-                "Authorization",
-                HeaderValue::from_str(format!("Token {}", auth.key).as_str())?,
-            );
-
-            // set default client operation
-            let client = Arc::new(
-                reqwest::Client::builder()
-                    .default_headers(headers)
-                    .build()?,
-            );
-
-            Ok(FireboardApiClient { api_base, client })
+            FireboardApiClient::with_token(auth.key)
         } else {
             Err(anyhow::anyhow!(
                 "Error authenticating with Fireboard API! Check your username and password. {:?}", auth_result.unwrap_err()
@@ -179,6 +253,46 @@ impl FireboardApiClient {
         }
     }
 
+    /// Uses a token issued beforehand without logging in, see [`Self::token_accepted`] to
+    /// check it's still valid
+    pub fn with_token(token: String) -> Result<FireboardApiClient> {
+        let api_base = Url::parse("https://fireboard.io/api/")?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", HeaderValue::from_static("application/json"));
+        headers.insert("User-Agent", HeaderValue::from_static(USER_AGENT));
+        headers.insert(
+            "Authorization",
+            HeaderValue::from_str(format!("Token {}", token).as_str())?,
+        );
+
+        // set default client operation
+        let client = Arc::new(
+            reqwest::Client::builder()
+                .default_headers(headers)
+                .build()?,
+        );
+
+        Ok(FireboardApiClient { api_base, client, token })
+    }
+
+    /// the token the client authenticates with, e.g. to save it after logging in
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Whether the api still accepts the client's token, `false` when it has been revoked
+    /// or expired. Costs one request.
+    pub async fn token_accepted(&self) -> Result<bool> {
+        let endpoint = self.api_base.join("rest-auth/user/")?;
+        let response = self.client.get(endpoint).send().await?;
+        match response.status() {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(anyhow::anyhow!("Error checking the Fireboard API token: {}", status)),
+        }
+    }
+
     pub fn devices(&self) -> DevicesEndpoint<'_> {
         DevicesEndpoint(self)
     }
@@ -254,4 +368,37 @@ impl<'c> DevicesEndpoint<'c> {
             Ok(Some(json_output))
         }
     }
+
+    /// `degreetype` is the unit of the device the drive is plugged into, which the api
+    /// expects the setpoint in
+    pub async fn set_drive_setpoint(
+        &self,
+        device_uuid: &str,
+        setpoint: Temperature,
+        degreetype: DegreeType,
+    ) -> Result<()> {
+        let setpoint = setpoint.to_unit(degreetype).value();
+        self.update_drive(device_uuid, json!({ "setpoint": setpoint }))
+            .await
+    }
+
+    pub async fn set_drive_mode(&self, device_uuid: &str, modetype: DriveModeType) -> Result<()> {
+        self.update_drive(device_uuid, json!({ "modetype": modetype.to_string() }))
+            .await
+    }
+
+    async fn update_drive(&self, device_uuid: &str, body: Value) -> Result<()> {
+        let base_endpoint = self.endpoint()?;
+        let endpoint = Url::parse(&format!("{}/{}/drive.json", base_endpoint, device_uuid))?;
+        debug!("updating drive settings for {}: {}", device_uuid, body);
+
+        let response = self.0.client.post(endpoint).json(&body).send().await?;
+        if !response.status().is_success() {
+            let status = response.status().to_string();
+            error!("Error updating drive settings: {}", status);
+            error!("{}", response.text().await?);
+            return Err(anyhow::anyhow!("Error updating drive settings: {}", status));
+        }
+        Ok(())
+    }
 }
@@ -1,49 +1,130 @@
 //! # Fireboard Watcher
-//! 
+//!
 //! This module is responsible for watching the Fireboard API and updating the MQTT broker with the latest data
 //! as changes occur. It also handles the MQTT discovery process for new devices and channels.
-use chrono::Local;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use chrono::{DateTime, Duration, Local};
 use rumqttc::v5::mqttbytes::v5::LastWill;
 use rumqttc::v5::mqttbytes::QoS;
+use strum::IntoEnumIterator;
 use tokio::sync::mpsc::Sender;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 
-use crate::config::Fb2MqttConfig;
-use crate::constants::{FIREBOARD_DEVICELOG_UPDATE_INTERVAL_MINUTES, OFF, OFFLINE, ON, ONLINE};
+use crate::account_status::AccountStatus;
+use crate::alarm::{AlarmKind, ALARM_KINDS};
+use crate::battery::BatteryModel;
+use crate::channel_stats::{self, ChannelStats, STAT_NAMES};
+use crate::config::{Fb2MqttConfig, FireboardAccount};
+use crate::constants::{FIREBOARD_API_REQUESTS_PER_HOUR, OFF, OFFLINE, ON, ONLINE, PAYLOAD_RESET};
+use crate::cook_eta;
+use crate::cook_session::{CookReading, CookSessionTracker, CookTransition};
 use crate::device::{
-    MQTTDiscoveryAvailabilityEntry, MQTTDiscoveryBinarySensor, MQTTDiscoveryDevice,
-    MQTTDiscoverySensor,
+    EntityCategory, MQTTDiscoveryBinarySensor, MQTTDiscoveryClimate, MQTTDiscoveryDevice,
+    MQTTDiscoveryDeviceTrigger, MQTTDiscoveryEntity, MQTTDiscoveryEntityBuilder,
+    MQTTDiscoveryEvent, MQTTDiscoveryNumber, MQTTDiscoverySelect, MQTTDiscoverySensor,
+};
+use crate::device_event::{
+    DeviceEvent, DeviceEventType, CHANNEL_TRIGGER_EVENTS, CHANNEL_TRIGGER_VALUE_TEMPLATE,
+    COOK_EVENTS, DEVICE_TRIGGER_EVENTS, DEVICE_TRIGGER_VALUE_TEMPLATE,
 };
+use crate::device_state::{ChannelState, DeviceState};
 use crate::drive::DriveAttributes;
-use crate::fireboard_api::{DriveModeType, FireboardApiClient, FireboardApiDevice};
-use crate::mqtt_action::MQTTAction;
+use crate::fire_out::{FireOutCriteria, FireOutDetector, FireOutReading, FireOutTransition};
+use crate::fireboard_api::{DegreeType, DriveModeType, FireboardApiClient, FireboardApiDevice};
+use crate::lid_open::{LidOpenCriteria, LidOpenDetector};
+use crate::mqtt_action::{MQTTAction, MQTTCommand};
+use crate::reading_filter::{ReadingCriteria, Rejection};
+use crate::stall::{StallCriteria, StallTransition};
+use crate::state_store::{ChannelSettings, StateStore};
+use crate::temperature::Temperature;
+use crate::token_store::TokenStore;
 use crate::utils::f32_to_u8_pct;
 
+/// a rate of change sensor published for each channel
+struct RateUnit {
+    name: &'static str,
+    label: &'static str,
+    unit: &'static str,
+    /// multiplier from degrees per minute
+    multiplier: f32,
+}
+
+const RATE_UNITS: [RateUnit; 2] = [
+    RateUnit {
+        name: "minute",
+        label: "Minute",
+        unit: "min",
+        multiplier: 1.0,
+    },
+    RateUnit {
+        name: "hour",
+        label: "Hour",
+        unit: "h",
+        multiplier: 60.0,
+    },
+];
+
+/// Which account each device is published by, shared between the accounts' watchers so a
+/// device on more than one account is only published once
+pub type DeviceClaims = Arc<Mutex<HashMap<String, String>>>;
 
 pub struct FireboardWatcher {
     online_device_count: u8,
+    /// requests the last poll made to the fireboard api, the device list plus a drivelog
+    /// per device with drive support enabled
+    poll_requests: u64,
     fb_client: FireboardApiClient,
     tx: Sender<MQTTAction>,
     cfg: Fb2MqttConfig,
+    account: FireboardAccount,
+    /// the mqtt base topic, plus the account's namespace
+    base_topic: String,
+    device_claims: DeviceClaims,
+    /// devices seen since startup, keyed by hardware_id
+    devices: HashMap<String, DeviceState>,
+    /// excluded devices (by hardware_id), hidden channels (by channel id) and, without drive
+    /// support, devices' drive entities (by `<hardware_id>_drive`) already removed from home
+    /// assistant
+    removed: HashSet<String>,
+    /// devices (by hardware_id) found on this account that another account publishes, so
+    /// that's only warned about once
+    shared: HashSet<String>,
+    state_store: StateStore,
 }
 
 impl FireboardWatcher {
-    pub async fn new(cfg: &Fb2MqttConfig, tx: Sender<MQTTAction>) -> Result<FireboardWatcher> {
-        let fb_client = FireboardApiClient::new(
-            cfg.fireboardaccount_email.clone(),
-            cfg.fireboardaccount_password.clone(),
-        )
-        .await?;
-        debug!("client authenticated successfully");
+    pub async fn new(
+        cfg: &Fb2MqttConfig,
+        account: &FireboardAccount,
+        device_claims: DeviceClaims,
+        tx: Sender<MQTTAction>,
+    ) -> Result<FireboardWatcher> {
+        let fb_client = FireboardWatcher::authenticate(cfg, account).await?;
+        debug!(
+            "client for account {} authenticated successfully",
+            account.name()
+        );
 
         let mut fb_watcher = FireboardWatcher {
             online_device_count: 0,
+            poll_requests: 0,
             fb_client,
             tx,
             cfg: cfg.clone(),
+            account: account.clone(),
+            base_topic: cfg.account_base_topic(account),
+            device_claims,
+            devices: HashMap::new(),
+            removed: HashSet::new(),
+            shared: HashSet::new(),
+            state_store: StateStore::load(&cfg.account_state_dir(account)),
         };
         fb_watcher.init().await;
         Ok(fb_watcher)
@@ -53,6 +134,21 @@ impl FireboardWatcher {
         self.online_device_count
     }
 
+    /// Seconds until the next poll. The configured intervals are checked against the api's
+    /// rate limit assuming a single drive, so with more devices the poll is slowed down to
+    /// keep the requests it makes under the limit.
+    pub fn poll_interval(&self) -> u64 {
+        let configured = if self.online_device_count == 0 {
+            // poll less often when no devices are online
+            self.cfg.idle_poll_interval_secs
+        } else if self.cfg.fireboard_enable_drive {
+            self.cfg.drive_poll_interval_secs
+        } else {
+            self.cfg.poll_interval_secs
+        };
+        configured.max(self.poll_requests * 3600 / FIREBOARD_API_REQUESTS_PER_HOUR)
+    }
+
     pub fn get_topic_bridge_availablility(&self) -> String {
         format!("{}/bridge/availability", self.cfg.mqtt_base_topic)
     }
@@ -71,8 +167,43 @@ impl FireboardWatcher {
         )
     }
 
+    pub fn get_discovery_number_base_topic(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/number/{}",
+            self.cfg.mqtt_discovery_topic, device_identifier
+        )
+    }
+
+    pub fn get_discovery_select_base_topic(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/select/{}",
+            self.cfg.mqtt_discovery_topic, device_identifier
+        )
+    }
+
+    pub fn get_discovery_climate_base_topic(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/climate/{}",
+            self.cfg.mqtt_discovery_topic, device_identifier
+        )
+    }
+
+    pub fn get_discovery_event_base_topic(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/event/{}",
+            self.cfg.mqtt_discovery_topic, device_identifier
+        )
+    }
+
+    pub fn get_discovery_device_automation_base_topic(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/device_automation/{}",
+            self.cfg.mqtt_discovery_topic, device_identifier
+        )
+    }
+
     pub fn get_device_base_topic(&self, device_identifier: &String) -> String {
-        format!("{}/{}", self.cfg.mqtt_base_topic, device_identifier)
+        format!("{}/{}", self.base_topic, device_identifier)
     }
 
     pub fn get_topic_device_availablility(&self, device_identifier: &String) -> String {
@@ -93,6 +224,75 @@ impl FireboardWatcher {
         )
     }
 
+    pub fn get_topic_device_battery_runtime(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/runtime",
+            self.get_topic_device_battery(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_battery_runtime_discovery(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/battery_runtime/config",
+            self.get_discovery_sensor_base_topic(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_battery_low(&self, device_identifier: &String) -> String {
+        format!("{}/low", self.get_topic_device_battery(device_identifier))
+    }
+
+    pub fn get_topic_device_battery_low_discovery(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/battery_low/config",
+            self.get_discovery_binary_sensor_base_topic(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_battery_charging(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/charging",
+            self.get_topic_device_battery(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_battery_charging_discovery(
+        &self,
+        device_identifier: &String,
+    ) -> String {
+        format!(
+            "{}/battery_charging/config",
+            self.get_discovery_binary_sensor_base_topic(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_rejected_readings(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/rejected_readings",
+            self.get_device_base_topic(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_rejected_readings_attributes(
+        &self,
+        device_identifier: &String,
+    ) -> String {
+        format!(
+            "{}/attributes",
+            self.get_topic_device_rejected_readings(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_rejected_readings_discovery(
+        &self,
+        device_identifier: &String,
+    ) -> String {
+        format!(
+            "{}/rejected_readings/config",
+            self.get_discovery_sensor_base_topic(device_identifier)
+        )
+    }
+
     pub fn get_topic_device_channel(&self, device_identifier: &String, channel: &usize) -> String {
         format!(
             "{}/channel_{}",
@@ -125,117 +325,1883 @@ impl FireboardWatcher {
         )
     }
 
-    pub fn get_topic_device_drive_discovery(&self, device_identifier: &String) -> String {
+    pub fn get_topic_device_channel_alarm_threshold(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+        kind: AlarmKind,
+    ) -> String {
         format!(
-            "{}/drive/config",
-            self.get_discovery_sensor_base_topic(device_identifier)
+            "{}/{}",
+            self.get_topic_device_channel(device_identifier, channel),
+            kind.threshold_name()
         )
     }
 
-    pub fn get_topic_device_drivemode_discovery(&self, device_identifier: &String) -> String {
+    pub fn get_topic_device_channel_alarm_threshold_command(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+        kind: AlarmKind,
+    ) -> String {
         format!(
-            "{}/drivemode/config",
-            self.get_discovery_sensor_base_topic(device_identifier)
+            "{}/set",
+            self.get_topic_device_channel_alarm_threshold(device_identifier, channel, kind)
         )
     }
 
-    pub fn get_topic_device_drive_setpoint_discovery(&self, device_identifier: &String) -> String {
+    pub fn get_topic_device_channel_alarm_threshold_discovery(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+        kind: AlarmKind,
+    ) -> String {
         format!(
-            "{}/drive_setpoint/config",
-            self.get_discovery_sensor_base_topic(device_identifier)
+            "{}/channel_{}_{}/config",
+            self.get_discovery_number_base_topic(device_identifier),
+            channel,
+            kind.threshold_name()
         )
     }
 
-    pub fn get_topic_device_drive_lidpaused_discovery(&self, device_identifier: &String) -> String {
+    pub fn get_topic_device_channel_alarm(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+        kind: AlarmKind,
+    ) -> String {
         format!(
-            "{}/drive_lidpaused/config",
-            self.get_discovery_binary_sensor_base_topic(device_identifier)
+            "{}/{}",
+            self.get_topic_device_channel(device_identifier, channel),
+            kind.state_name()
         )
     }
 
-    pub fn get_topic_device_drive(&self, device_identifier: &String) -> String {
-        format!("{}/drive", self.get_device_base_topic(device_identifier))
+    pub fn get_topic_device_channel_alarm_discovery(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+        kind: AlarmKind,
+    ) -> String {
+        format!(
+            "{}/channel_{}_{}/config",
+            self.get_discovery_binary_sensor_base_topic(device_identifier),
+            channel,
+            kind.state_name()
+        )
     }
 
-    pub fn get_topic_device_drive_availability(&self, device_identifier: &String) -> String {
+    pub fn get_topic_device_channel_rate(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+        rate_name: &str,
+    ) -> String {
         format!(
-            "{}/availability",
-            self.get_topic_device_drive(device_identifier)
+            "{}/rate_per_{}",
+            self.get_topic_device_channel(device_identifier, channel),
+            rate_name
         )
     }
 
-    pub fn get_topic_device_drive_setpoint_availability(
+    pub fn get_topic_device_channel_rate_discovery(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+        rate_name: &str,
+    ) -> String {
+        format!(
+            "{}/channel_{}_rate_per_{}/config",
+            self.get_discovery_sensor_base_topic(device_identifier),
+            channel,
+            rate_name
+        )
+    }
+
+    pub fn get_topic_device_channel_stall(
         &self,
         device_identifier: &String,
+        channel: &usize,
     ) -> String {
         format!(
-            "{}/setpoint_availability",
-            self.get_topic_device_drive(device_identifier)
+            "{}/stall",
+            self.get_topic_device_channel(device_identifier, channel)
         )
     }
 
-    pub fn get_topic_device_drive_state(&self, device_identifier: &String) -> String {
-        format!("{}/state", self.get_topic_device_drive(device_identifier))
+    pub fn get_topic_device_channel_stall_discovery(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+    ) -> String {
+        format!(
+            "{}/channel_{}_stall/config",
+            self.get_discovery_binary_sensor_base_topic(device_identifier),
+            channel
+        )
     }
 
-    pub fn get_topic_device_drive_mode(&self, device_identifier: &String) -> String {
-        format!("{}/mode", self.get_topic_device_drive(device_identifier))
+    pub fn get_topic_device_channel_stall_duration(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+    ) -> String {
+        format!(
+            "{}/stall_duration",
+            self.get_topic_device_channel(device_identifier, channel)
+        )
     }
 
-    pub fn get_topic_device_drive_setpoint(&self, device_identifier: &String) -> String {
+    pub fn get_topic_device_channel_stall_duration_discovery(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+    ) -> String {
         format!(
-            "{}/setpoint",
-            self.get_topic_device_drive(device_identifier)
+            "{}/channel_{}_stall_duration/config",
+            self.get_discovery_sensor_base_topic(device_identifier),
+            channel
         )
     }
 
-    pub fn get_topic_device_drive_lidpaused(&self, device_identifier: &String) -> String {
+    pub fn get_topic_device_channel_statistics(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+    ) -> String {
         format!(
-            "{}/lidpaused",
-            self.get_topic_device_drive(device_identifier)
+            "{}/statistics",
+            self.get_topic_device_channel(device_identifier, channel)
+        )
+    }
+
+    pub fn get_topic_device_channel_statistic_discovery(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+        window_name: &str,
+        stat_name: &str,
+    ) -> String {
+        format!(
+            "{}/channel_{}_{}_{}/config",
+            self.get_discovery_sensor_base_topic(device_identifier),
+            channel,
+            window_name,
+            stat_name
+        )
+    }
+
+    pub fn get_topic_device_channel_target(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+    ) -> String {
+        format!(
+            "{}/target",
+            self.get_topic_device_channel(device_identifier, channel)
+        )
+    }
+
+    pub fn get_topic_device_channel_target_command(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+    ) -> String {
+        format!(
+            "{}/set",
+            self.get_topic_device_channel_target(device_identifier, channel)
+        )
+    }
+
+    pub fn get_topic_device_channel_target_discovery(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+    ) -> String {
+        format!(
+            "{}/channel_{}_target/config",
+            self.get_discovery_number_base_topic(device_identifier),
+            channel
+        )
+    }
+
+    pub fn get_topic_device_channel_eta(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+    ) -> String {
+        format!(
+            "{}/eta",
+            self.get_topic_device_channel(device_identifier, channel)
+        )
+    }
+
+    pub fn get_topic_device_channel_eta_attributes(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+    ) -> String {
+        format!(
+            "{}/attributes",
+            self.get_topic_device_channel_eta(device_identifier, channel)
+        )
+    }
+
+    pub fn get_topic_device_channel_eta_discovery(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+    ) -> String {
+        format!(
+            "{}/channel_{}_eta/config",
+            self.get_discovery_sensor_base_topic(device_identifier),
+            channel
+        )
+    }
+
+    pub fn get_topic_device_drive_discovery(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/drive/config",
+            self.get_discovery_sensor_base_topic(device_identifier)
         )
     }
 
-    pub fn get_topic_device_drive_attributes(&self, device_identifier: &String) -> String {
-        format!(
-            "{}/attributes",
-            self.get_topic_device_drive(device_identifier)
-        )
-    }
+    pub fn get_topic_device_drivemode_discovery(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/drivemode/config",
+            self.get_discovery_select_base_topic(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive_setpoint_discovery(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/drive_setpoint/config",
+            self.get_discovery_number_base_topic(device_identifier)
+        )
+    }
+
+    /// drive mode and setpoint used to be discovered as read-only sensors
+    pub fn get_topic_device_legacy_drive_discovery(
+        &self,
+        device_identifier: &String,
+    ) -> Vec<String> {
+        vec![
+            format!(
+                "{}/drivemode/config",
+                self.get_discovery_sensor_base_topic(device_identifier)
+            ),
+            format!(
+                "{}/drive_setpoint/config",
+                self.get_discovery_sensor_base_topic(device_identifier)
+            ),
+        ]
+    }
+
+    pub fn get_topic_device_drive_climate_discovery(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/drive/config",
+            self.get_discovery_climate_base_topic(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive_lidpaused_discovery(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/drive_lidpaused/config",
+            self.get_discovery_binary_sensor_base_topic(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive(&self, device_identifier: &String) -> String {
+        format!("{}/drive", self.get_device_base_topic(device_identifier))
+    }
+
+    pub fn get_topic_device_drive_availability(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/availability",
+            self.get_topic_device_drive(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive_setpoint_availability(
+        &self,
+        device_identifier: &String,
+    ) -> String {
+        format!(
+            "{}/setpoint_availability",
+            self.get_topic_device_drive(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive_state(&self, device_identifier: &String) -> String {
+        format!("{}/state", self.get_topic_device_drive(device_identifier))
+    }
+
+    pub fn get_topic_device_drive_mode(&self, device_identifier: &String) -> String {
+        format!("{}/mode", self.get_topic_device_drive(device_identifier))
+    }
+
+    pub fn get_topic_device_drive_mode_command(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/set",
+            self.get_topic_device_drive_mode(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive_setpoint(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/setpoint",
+            self.get_topic_device_drive(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive_setpoint_command(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/set",
+            self.get_topic_device_drive_setpoint(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive_target_temperature(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/target_temperature",
+            self.get_topic_device_drive(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive_current_temperature(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/current_temperature",
+            self.get_topic_device_drive(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive_climate_mode(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/climate_mode",
+            self.get_topic_device_drive(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive_climate_mode_command(
+        &self,
+        device_identifier: &String,
+    ) -> String {
+        format!(
+            "{}/set",
+            self.get_topic_device_drive_climate_mode(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive_action(&self, device_identifier: &String) -> String {
+        format!("{}/action", self.get_topic_device_drive(device_identifier))
+    }
+
+    /// the topic a device's events (a stall starting, a probe being unplugged, etc) are
+    /// published to as unretained json, which its device triggers also listen on
+    pub fn get_topic_device_events(&self, device_identifier: &String) -> String {
+        format!("{}/events", self.get_device_base_topic(device_identifier))
+    }
+
+    pub fn get_topic_device_cook(&self, device_identifier: &String) -> String {
+        format!("{}/cook", self.get_device_base_topic(device_identifier))
+    }
+
+    pub fn get_topic_device_cook_attributes(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/attributes",
+            self.get_topic_device_cook(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_cook_discovery(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/cook/config",
+            self.get_discovery_sensor_base_topic(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_cook_event(&self, device_identifier: &String) -> String {
+        format!("{}/event", self.get_topic_device_cook(device_identifier))
+    }
+
+    pub fn get_topic_device_cook_event_discovery(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/cook/config",
+            self.get_discovery_event_base_topic(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_fire_out(&self, device_identifier: &String) -> String {
+        format!("{}/fire_out", self.get_device_base_topic(device_identifier))
+    }
+
+    pub fn get_topic_device_fire_out_discovery(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/fire_out/config",
+            self.get_discovery_binary_sensor_base_topic(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_lid(&self, device_identifier: &String) -> String {
+        format!("{}/lid", self.get_device_base_topic(device_identifier))
+    }
+
+    pub fn get_topic_device_lid_attributes(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/attributes",
+            self.get_topic_device_lid(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_lid_discovery(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/lid/config",
+            self.get_discovery_binary_sensor_base_topic(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_trigger_discovery(
+        &self,
+        device_identifier: &String,
+        trigger_id: &str,
+    ) -> String {
+        format!(
+            "{}/{}/config",
+            self.get_discovery_device_automation_base_topic(device_identifier),
+            trigger_id
+        )
+    }
+
+    /// matches every command topic (drive and channel) for a device
+    pub fn get_topic_device_command_filter(&self, device_identifier: &String) -> String {
+        format!("{}/+/+/set", self.get_device_base_topic(device_identifier))
+    }
+
+    /// The discovery configs of a device's drive entities, which are only published with
+    /// drive support enabled
+    pub fn get_topics_device_drive_discovery(&self, device_identifier: &String) -> Vec<String> {
+        vec![
+            self.get_topic_device_drive_discovery(device_identifier),
+            self.get_topic_device_drivemode_discovery(device_identifier),
+            self.get_topic_device_drive_setpoint_discovery(device_identifier),
+            self.get_topic_device_drive_climate_discovery(device_identifier),
+            self.get_topic_device_drive_lidpaused_discovery(device_identifier),
+        ]
+    }
+
+    /// Every retained topic published for a device, not counting its channels: the discovery
+    /// configs and the retained states
+    pub fn get_topics_device_retained(&self, device_identifier: &String) -> Vec<String> {
+        let mut topics = vec![
+            self.get_topic_device_availablility(device_identifier),
+            self.get_topic_device_battery(device_identifier),
+            self.get_topic_device_drive_availability(device_identifier),
+            self.get_topic_device_battery_discovery(device_identifier),
+            self.get_topic_device_battery_runtime_discovery(device_identifier),
+            self.get_topic_device_battery_low_discovery(device_identifier),
+            self.get_topic_device_battery_charging_discovery(device_identifier),
+            self.get_topic_device_rejected_readings_discovery(device_identifier),
+            self.get_topic_device_cook_discovery(device_identifier),
+            self.get_topic_device_cook_event_discovery(device_identifier),
+            self.get_topic_device_fire_out_discovery(device_identifier),
+            self.get_topic_device_lid_discovery(device_identifier),
+        ];
+        topics.extend(self.get_topics_device_drive_discovery(device_identifier));
+        topics.extend(DEVICE_TRIGGER_EVENTS.iter().map(|event_type| {
+            self.get_topic_device_trigger_discovery(device_identifier, &event_type.to_string())
+        }));
+        topics
+    }
+
+    /// Every retained topic published for a channel: the discovery configs of all its
+    /// entities and its retained states
+    pub fn get_topics_device_channel_retained(
+        &self,
+        device_identifier: &String,
+        channel: &usize,
+    ) -> Vec<String> {
+        let mut topics = vec![
+            format!(
+                "{}/availability",
+                self.get_topic_device_channel(device_identifier, channel)
+            ),
+            self.get_topic_device_channel_target(device_identifier, channel),
+            self.get_topic_device_channel_discovery(device_identifier, channel),
+            self.get_topic_device_channel_stall_discovery(device_identifier, channel),
+            self.get_topic_device_channel_stall_duration_discovery(device_identifier, channel),
+            self.get_topic_device_channel_target_discovery(device_identifier, channel),
+            self.get_topic_device_channel_eta_discovery(device_identifier, channel),
+        ];
+        for kind in ALARM_KINDS {
+            topics.push(self.get_topic_device_channel_alarm_threshold(
+                device_identifier,
+                channel,
+                kind,
+            ));
+            topics.push(self.get_topic_device_channel_alarm_threshold_discovery(
+                device_identifier,
+                channel,
+                kind,
+            ));
+            topics.push(self.get_topic_device_channel_alarm_discovery(
+                device_identifier,
+                channel,
+                kind,
+            ));
+        }
+        for rate_unit in RATE_UNITS {
+            topics.push(self.get_topic_device_channel_rate_discovery(
+                device_identifier,
+                channel,
+                rate_unit.name,
+            ));
+        }
+        // the statistics sensors may have been turned off since, clear them anyway
+        for window in &self.cfg.stats_windows {
+            for (stat_name, _) in STAT_NAMES {
+                topics.push(self.get_topic_device_channel_statistic_discovery(
+                    device_identifier,
+                    channel,
+                    &window.name,
+                    stat_name,
+                ));
+            }
+        }
+        for event_type in CHANNEL_TRIGGER_EVENTS {
+            topics.push(self.get_topic_device_trigger_discovery(
+                device_identifier,
+                &format!("channel_{}_{}", channel, event_type),
+            ));
+        }
+        topics
+    }
+
+    pub fn get_topic_device_drive_lidpaused(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/lidpaused",
+            self.get_topic_device_drive(device_identifier)
+        )
+    }
+
+    pub fn get_topic_device_drive_attributes(&self, device_identifier: &String) -> String {
+        format!(
+            "{}/attributes",
+            self.get_topic_device_drive(device_identifier)
+        )
+    }
+
+    pub fn get_last_will(&self) -> LastWill {
+        let topic = self.get_topic_bridge_availablility();
+        LastWill {
+            topic: topic.into(),
+            message: OFFLINE.into(),
+            qos: QoS::AtLeastOnce,
+            retain: true,
+            properties: None,
+        }
+    }
+
+    async fn init(&mut self) {
+        // first we will set the availability of the bridge (this service)
+
+        self.tx
+            .send(MQTTAction::Publish {
+                topic: self.get_topic_bridge_availablility(),
+                qos: QoS::AtLeastOnce,
+                retain: true,
+                payload: ONLINE.into(),
+                props: None,
+            })
+            .await
+            .unwrap();
+    }
+
+    /// Uses the account's api token if it has one, checking it hasn't been revoked, or logs
+    /// in with its email and password. The token from a login is saved in the account's
+    /// state dir and reused on the next start for as long as the api accepts it.
+    async fn authenticate(
+        cfg: &Fb2MqttConfig,
+        account: &FireboardAccount,
+    ) -> Result<FireboardApiClient> {
+        let login = account.email.clone().zip(account.password.clone());
+        if let Some(token) = &account.api_token {
+            let fb_client = FireboardApiClient::with_token(token.clone())?;
+            if fb_client.token_accepted().await? {
+                return Ok(fb_client);
+            }
+            if login.is_none() {
+                return Err(anyhow!(
+                    "the fireboard api token for account {} was rejected, it has probably been revoked. Issue a new one, or use the account's email and password instead",
+                    account.name()
+                ));
+            }
+            warn!(
+                "the fireboard api token for account {} was rejected, logging in with its email and password instead",
+                account.name()
+            );
+        }
+        let Some((email, password)) = login else {
+            return Err(anyhow!(
+                "account {} has no api token or email and password",
+                account.name()
+            ));
+        };
+
+        let token_store = TokenStore::new(&cfg.account_state_dir(account));
+        if let Some(token) = token_store.load(&email) {
+            let fb_client = FireboardApiClient::with_token(token)?;
+            if fb_client.token_accepted().await? {
+                debug!(
+                    "reusing the saved auth token for account {}",
+                    account.name()
+                );
+                return Ok(fb_client);
+            }
+            info!(
+                "the saved auth token for account {} was rejected, logging in again",
+                account.name()
+            );
+            token_store.clear();
+        }
+        let fb_client = FireboardApiClient::login(email.clone(), password).await?;
+        if let Err(e) = token_store.save(&email, fb_client.token()) {
+            warn!(
+                "unable to save the auth token for account {}, it will log in again on the next start: {:?}",
+                account.name(),
+                e
+            );
+        }
+        Ok(fb_client)
+    }
+
+    /// Switches to a reloaded config. Everything reads its settings from the config as it
+    /// goes, so they apply from the next poll, apart from the channels' history which is
+    /// resized to the new windows here. After the mqtt connection has been replaced the
+    /// bridge's availability and the command subscriptions are sent again.
+    pub async fn reload(&mut self, cfg: &Fb2MqttConfig, reconnected: bool) {
+        self.cfg = cfg.clone();
+        let history_retention = self.history_retention();
+        for device_state in self.devices.values_mut() {
+            for channel_state in device_state.channels.values_mut() {
+                channel_state.history.set_retention(history_retention);
+            }
+        }
+        // newly excluded devices and hidden channels are removed on the next poll, and ones
+        // excluded again after being brought back are cleared again
+        self.removed.clear();
+        // recounted on the next poll, so the new intervals are checked against it
+        self.poll_requests = 0;
+        if reconnected {
+            self.init().await;
+            for hardware_id in self.devices.keys() {
+                self.tx
+                    .send(MQTTAction::Subscribe {
+                        topic: self.get_topic_device_command_filter(hardware_id),
+                        qos: QoS::AtLeastOnce,
+                        props: None,
+                    })
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    /// Called the first time a device shows up in the device list. Subscribes to the
+    /// device's command topics and removes discovery entries from older versions.
+    async fn add_device(&mut self, device: &FireboardApiDevice) {
+        let hardware_id = device.hardware_id.clone();
+        info!("adding device {} ({})", device.title, hardware_id);
+
+        for topic in self.get_topic_device_legacy_drive_discovery(&hardware_id) {
+            self.tx
+                .send(MQTTAction::Publish {
+                    topic,
+                    qos: QoS::AtLeastOnce,
+                    retain: true,
+                    payload: Bytes::new(),
+                    props: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        self.tx
+            .send(MQTTAction::Subscribe {
+                topic: self.get_topic_device_command_filter(&hardware_id),
+                qos: QoS::AtLeastOnce,
+                props: None,
+            })
+            .await
+            .unwrap();
+
+        self.devices.insert(
+            hardware_id.clone(),
+            DeviceState {
+                uuid: device.uuid.clone(),
+                degreetype: device.degreetype,
+                unit: self.output_unit(device.degreetype),
+                channels: HashMap::new(),
+                drive_tied_channel: None,
+                online: None,
+                cook: CookSessionTracker::default(),
+                fire_out: FireOutDetector::default(),
+                lid: LidOpenDetector::default(),
+                battery: BatteryModel::default(),
+            },
+        );
+
+        // let home assistant know about the persisted and configured alarm thresholds
+        for channel in &device.channels {
+            for kind in ALARM_KINDS {
+                self.publish_alarm_threshold(&hardware_id, &channel.channel, kind)
+                    .await;
+            }
+            self.publish_channel_target(&hardware_id, &channel.channel)
+                .await;
+        }
+    }
+
+    /// Applies the configured overrides to a device fresh from the api, so the rest of the
+    /// update only sees what should end up in home assistant: excluded devices are dropped,
+    /// hidden channels removed and renamed channels relabelled. Excluded devices and hidden
+    /// channels are also removed from home assistant, in case they were published before.
+    async fn apply_overrides(
+        &mut self,
+        mut device: FireboardApiDevice,
+    ) -> Option<FireboardApiDevice> {
+        let Some(device_override) = self
+            .cfg
+            .device_override(&device.hardware_id, &device.uuid)
+            .cloned()
+        else {
+            self.removed.remove(&device.hardware_id);
+            return Some(device);
+        };
+        if device_override.exclude {
+            debug!("skipping excluded device {}", device.hardware_id);
+            self.remove_device(&device).await;
+            return None;
+        }
+        self.removed.remove(&device.hardware_id);
+
+        let (hidden, channels) = device.channels.into_iter().partition(|channel| {
+            device_override
+                .channels
+                .get(&channel.channel)
+                .is_some_and(|channel_override| channel_override.hidden)
+        });
+        device.channels = channels;
+        for channel in hidden {
+            self.remove_channel(&device.hardware_id, &channel.channel)
+                .await;
+        }
+        for channel in &mut device.channels {
+            self.removed
+                .remove(&Self::channel_id(&device.hardware_id, &channel.channel));
+            if let Some(name) = device_override
+                .channels
+                .get(&channel.channel)
+                .and_then(|channel_override| channel_override.name.as_ref())
+            {
+                channel.channel_label = name.clone();
+            }
+        }
+        Some(device)
+    }
+
+    /// Forgets an excluded device and, once per run, clears everything retained for it and
+    /// its channels, so home assistant deletes its entities
+    async fn remove_device(&mut self, device: &FireboardApiDevice) {
+        let hardware_id = &device.hardware_id;
+        if self.devices.remove(hardware_id).is_some() {
+            self.tx
+                .send(MQTTAction::Unsubscribe {
+                    topic: self.get_topic_device_command_filter(hardware_id),
+                    props: None,
+                })
+                .await
+                .unwrap();
+            let mut device_claims = self.device_claims.lock().unwrap();
+            if device_claims.get(hardware_id).map(String::as_str) == Some(self.account.name()) {
+                device_claims.remove(hardware_id);
+            }
+        }
+        if !self.removed.insert(hardware_id.clone()) {
+            return;
+        }
+        info!(
+            "removing excluded device {} from home assistant",
+            hardware_id
+        );
+        let mut topics = self.get_topics_device_retained(hardware_id);
+        for channel in &device.channels {
+            topics.extend(self.get_topics_device_channel_retained(hardware_id, &channel.channel));
+        }
+        self.clear_retained(topics).await;
+    }
+
+    /// Forgets a hidden channel and, once per run, clears everything retained for it
+    async fn remove_channel(&mut self, hardware_id: &String, channel: &usize) {
+        if let Some(device_state) = self.devices.get_mut(hardware_id) {
+            device_state.channels.remove(channel);
+        }
+        if !self.removed.insert(Self::channel_id(hardware_id, channel)) {
+            return;
+        }
+        info!(
+            "removing hidden channel {} of {} from home assistant",
+            channel, hardware_id
+        );
+        self.clear_retained(self.get_topics_device_channel_retained(hardware_id, channel))
+            .await;
+    }
+
+    /// Publishes an empty retained payload to each topic, which deletes the retained message
+    /// and, for a discovery config, the entity in home assistant
+    async fn clear_retained(&self, topics: Vec<String>) {
+        for topic in topics {
+            self.publish(topic, QoS::AtLeastOnce, true, Bytes::new())
+                .await;
+        }
+    }
+
+    /// The unit temperatures for a device are published in
+    fn output_unit(&self, degreetype: DegreeType) -> DegreeType {
+        self.cfg.temperature_unit.unwrap_or(degreetype)
+    }
+
+    /// A channel's persisted settings, in the device's output unit
+    fn channel_settings(&self, hardware_id: &String, channel: &usize) -> ChannelSettings {
+        let settings = self
+            .state_store
+            .channel(&Self::channel_id(hardware_id, channel));
+        match self.devices.get(hardware_id) {
+            Some(device_state) => settings.to_unit(device_state.unit, device_state.degreetype),
+            None => settings,
+        }
+    }
+
+    /// Applies `update` to a channel's persisted settings, in the device's output unit
+    fn update_channel_settings<F>(
+        &mut self,
+        hardware_id: &String,
+        channel: &usize,
+        update: F,
+    ) -> Result<ChannelSettings>
+    where
+        F: FnOnce(&mut ChannelSettings),
+    {
+        let units = self
+            .devices
+            .get(hardware_id)
+            .map(|device_state| (device_state.unit, device_state.degreetype));
+        self.state_store
+            .update_channel(&Self::channel_id(hardware_id, channel), |settings| {
+                if let Some((unit, degreetype)) = units {
+                    *settings = settings.to_unit(unit, degreetype);
+                }
+                update(settings)
+            })
+    }
+
+    fn channel_id(hardware_id: &String, channel: &usize) -> String {
+        format!("{}_channel_{}", hardware_id, channel)
+    }
+
+    async fn publish(&self, topic: String, qos: QoS, retain: bool, payload: impl Into<Bytes>) {
+        self.tx
+            .send(MQTTAction::Publish {
+                topic,
+                qos,
+                retain,
+                payload: payload.into(),
+                props: None,
+            })
+            .await
+            .unwrap();
+    }
+
+    /// A channel's alarm threshold, in the device's output unit. One set over mqtt wins over
+    /// the configured one.
+    fn alarm_threshold(
+        &self,
+        hardware_id: &String,
+        channel: &usize,
+        kind: AlarmKind,
+    ) -> Option<f32> {
+        kind.threshold(&self.channel_settings(hardware_id, channel))
+            .or_else(|| {
+                let device_state = self.devices.get(hardware_id)?;
+                self.cfg.alarm_threshold(
+                    hardware_id,
+                    &device_state.uuid,
+                    *channel,
+                    kind,
+                    device_state.unit,
+                )
+            })
+    }
+
+    async fn publish_alarm_threshold(
+        &self,
+        hardware_id: &String,
+        channel: &usize,
+        kind: AlarmKind,
+    ) {
+        self.publish(
+            self.get_topic_device_channel_alarm_threshold(hardware_id, channel, kind),
+            QoS::AtLeastOnce,
+            true,
+            self.alarm_threshold(hardware_id, channel, kind)
+                .map(|threshold| threshold.to_string())
+                .unwrap_or(PAYLOAD_RESET.to_string()),
+        )
+        .await;
+    }
+
+    /// The target temperature for a channel. One set over mqtt wins over the configured one.
+    fn channel_target(&self, hardware_id: &String, channel: &usize) -> Option<f32> {
+        self.channel_settings(hardware_id, channel)
+            .target
+            .or_else(|| {
+                let device_state = self.devices.get(hardware_id)?;
+                self.cfg
+                    .channel_target(hardware_id, *channel, device_state.unit)
+            })
+    }
+
+    /// The channel measuring the pit temperature, the configured one or the one tied to a drive
+    fn pit_channel(&self, hardware_id: &String) -> Option<usize> {
+        self.cfg.pit_channel.or_else(|| {
+            self.devices
+                .get(hardware_id)
+                .and_then(|device_state| device_state.drive_tied_channel)
+        })
+    }
+
+    async fn publish_channel_target(&self, hardware_id: &String, channel: &usize) {
+        self.publish(
+            self.get_topic_device_channel_target(hardware_id, channel),
+            QoS::AtLeastOnce,
+            true,
+            self.channel_target(hardware_id, channel)
+                .map(|target| target.to_string())
+                .unwrap_or(PAYLOAD_RESET.to_string()),
+        )
+        .await;
+    }
+
+    /// Sets (or clears, with an empty or `None` payload) the target temperature for a channel
+    async fn set_channel_target(&mut self, hardware_id: &String, channel: usize, payload: &str) {
+        let target = if payload.is_empty() || payload.eq_ignore_ascii_case(PAYLOAD_RESET) {
+            None
+        } else {
+            match payload.parse::<f32>() {
+                Ok(target) => Some(target),
+                Err(e) => {
+                    warn!(
+                        "invalid target '{}' for {} channel {}: {}",
+                        payload, hardware_id, channel, e
+                    );
+                    return;
+                }
+            }
+        };
+
+        info!(
+            "setting target for {} channel {} to {:?}",
+            hardware_id, channel, target
+        );
+        if let Err(err) =
+            self.update_channel_settings(hardware_id, &channel, |settings| settings.target = target)
+        {
+            error!("Error saving channel target: {:?}", err);
+        }
+        self.publish_channel_target(hardware_id, &channel).await;
+    }
+
+    /// Sets (or clears, with an empty or `None` payload) an alarm threshold for a channel
+    async fn set_alarm_threshold(
+        &mut self,
+        hardware_id: &String,
+        channel: usize,
+        kind: AlarmKind,
+        payload: &str,
+    ) {
+        let threshold = if payload.is_empty() || payload.eq_ignore_ascii_case(PAYLOAD_RESET) {
+            None
+        } else {
+            match payload.parse::<f32>() {
+                Ok(threshold) => Some(threshold),
+                Err(e) => {
+                    warn!(
+                        "invalid {} threshold '{}' for {} channel {}: {}",
+                        kind.threshold_name(),
+                        payload,
+                        hardware_id,
+                        channel,
+                        e
+                    );
+                    return;
+                }
+            }
+        };
+
+        info!(
+            "setting {} threshold for {} channel {} to {:?}",
+            kind.threshold_name(),
+            hardware_id,
+            channel,
+            threshold
+        );
+        if let Err(err) = self.update_channel_settings(hardware_id, &channel, |settings| {
+            kind.set_threshold(settings, threshold)
+        }) {
+            error!("Error saving alarm threshold: {:?}", err);
+        }
+        // clearing it goes back to the configured threshold, if there is one
+        self.publish_alarm_threshold(hardware_id, &channel, kind)
+            .await;
+    }
+
+    /// Publishes an event to the device's event topic
+    async fn publish_event(&self, hardware_id: &String, event: DeviceEvent) {
+        info!(
+            "{} event for {}: {:?}",
+            event.event_type, hardware_id, event.details
+        );
+        self.publish(
+            self.get_topic_device_events(hardware_id),
+            QoS::AtLeastOnce,
+            false,
+            event,
+        )
+        .await;
+    }
+
+    /// Publishes a device online/offline event when the device's state changes. Nothing is
+    /// published the first time a device is seen.
+    async fn update_device_online(&mut self, hardware_id: &String, online: bool) {
+        let Some(device_state) = self.devices.get_mut(hardware_id) else {
+            return;
+        };
+        let was_online = device_state.online.replace(online);
+        if was_online.is_none_or(|was_online| was_online == online) {
+            return;
+        }
+
+        let event_type = if online {
+            DeviceEventType::DeviceOnline
+        } else {
+            DeviceEventType::DeviceOffline
+        };
+        self.publish_event(hardware_id, DeviceEvent::new(event_type, Local::now()))
+            .await;
+    }
+
+    /// Publishes a probe connected/disconnected event when a channel's probe is plugged in
+    /// or unplugged. Nothing is published the first time a channel is seen.
+    async fn update_channel_connected(
+        &mut self,
+        hardware_id: &String,
+        channel: usize,
+        channel_label: &str,
+        connected: bool,
+    ) {
+        let Some(channel_state) = self.channel_state(hardware_id, channel) else {
+            return;
+        };
+        let was_connected = channel_state.connected.replace(connected);
+        if was_connected.is_none_or(|was_connected| was_connected == connected) {
+            return;
+        }
+
+        let event_type = if connected {
+            DeviceEventType::ProbeConnected
+        } else {
+            DeviceEventType::ProbeDisconnected
+        };
+        self.publish_event(
+            hardware_id,
+            DeviceEvent::new(event_type, Local::now())
+                .channel(channel)
+                .detail("channel_label", channel_label),
+        )
+        .await;
+    }
+
+    /// Runs a poll through the device's cook session tracker, publishing the current cook
+    /// and a cook started/ended event when a cook starts or ends
+    async fn update_cook_session(&mut self, hardware_id: &String, reading: CookReading<'_>) {
+        let now = Local::now();
+        let end_delay = Duration::seconds(self.cfg.cook_end_delay_secs as i64);
+        let Some(device_state) = self.devices.get_mut(hardware_id) else {
+            return;
+        };
+        let start_temp = self.cfg.temperatures(device_state.unit).cook_start_temp;
+        let transition = device_state
+            .cook
+            .evaluate(&reading, start_temp, end_delay, now);
+        let started = device_state.cook.current().map(|session| session.started);
+        let summary = device_state.cook.summary(now);
+
+        self.publish(
+            self.get_topic_device_cook(hardware_id),
+            QoS::AtMostOnce,
+            false,
+            started
+                .map(|started| started.to_rfc3339())
+                .unwrap_or(PAYLOAD_RESET.to_string()),
+        )
+        .await;
+        self.publish(
+            self.get_topic_device_cook_attributes(hardware_id),
+            QoS::AtMostOnce,
+            false,
+            serde_json::to_string(&summary).unwrap(),
+        )
+        .await;
+
+        let event = match transition {
+            Some(CookTransition::Started(started)) => {
+                DeviceEvent::new(DeviceEventType::CookStarted, now)
+                    .detail("started", started.to_rfc3339())
+            }
+            Some(CookTransition::Ended(summary)) => {
+                DeviceEvent::new(DeviceEventType::CookEnded, now).details(&summary)
+            }
+            None => return,
+        };
+        self.publish(
+            self.get_topic_device_cook_event(hardware_id),
+            QoS::AtLeastOnce,
+            false,
+            event.clone(),
+        )
+        .await;
+        self.publish_event(hardware_id, event).await;
+    }
+
+    /// Runs a poll through the device's fire out detector. Only runs during a cook, and only
+    /// when there's a pit channel to watch.
+    async fn update_fire_out(
+        &mut self,
+        hardware_id: &String,
+        pit_temp: Option<f32>,
+        drive: Option<(f32, Option<f32>)>,
+    ) {
+        let now = Local::now();
+        let pit_channel = self.pit_channel(hardware_id);
+        let window = Duration::seconds(self.cfg.fire_out_window_secs as i64);
+        let drive_saturation = self.cfg.fire_out_drive_saturation;
+        let Some(device_state) = self.devices.get_mut(hardware_id) else {
+            return;
+        };
+        let criteria = FireOutCriteria {
+            window,
+            min_fall_rate_per_hour: self
+                .cfg
+                .temperatures(device_state.unit)
+                .fire_out_min_fall_rate,
+            drive_saturation,
+        };
+        let pit_rate_per_hour = pit_channel
+            .and_then(|pit_channel| device_state.channels.get(&pit_channel))
+            .and_then(|channel_state| channel_state.rate_per_minute)
+            .map(|rate| rate * 60.0);
+
+        let cooking = device_state.cook.current().is_some();
+        let transition = match pit_temp {
+            Some(pit_temp) if cooking => device_state.fire_out.evaluate(
+                &FireOutReading {
+                    pit_temp,
+                    pit_rate_per_hour,
+                    driveper: drive.map(|(driveper, _)| driveper),
+                    setpoint: drive.and_then(|(_, setpoint)| setpoint),
+                },
+                &criteria,
+                now,
+            ),
+            _ => {
+                device_state.fire_out.reset();
+                None
+            }
+        };
+        let out = device_state.fire_out.out();
+
+        self.publish(
+            self.get_topic_device_fire_out(hardware_id),
+            QoS::AtMostOnce,
+            false,
+            if out { ON } else { OFF },
+        )
+        .await;
+
+        let event = match transition {
+            Some(FireOutTransition::Out { since }) => {
+                DeviceEvent::new(DeviceEventType::FireOut, now)
+                    .detail("since", since.to_rfc3339())
+                    .detail("pit_temp", pit_temp)
+                    .detail("pit_rate_per_hour", pit_rate_per_hour)
+                    .detail("driveper", drive.map(|(driveper, _)| driveper))
+            }
+            Some(FireOutTransition::Recovered { since }) => {
+                DeviceEvent::new(DeviceEventType::FireRecovered, now)
+                    .detail("since", since.to_rfc3339())
+                    .detail("pit_temp", pit_temp)
+            }
+            None => return,
+        };
+        self.publish_event(hardware_id, event).await;
+    }
+
+    /// Runs the pit channel's latest reading through the device's lid open detector
+    async fn update_lid(&mut self, hardware_id: &String, pit_temp: Option<f32>) {
+        let now = Local::now();
+        let pit_channel = self.pit_channel(hardware_id);
+        let window = Duration::seconds(self.cfg.lid_open_window_secs as i64);
+        let Some(device_state) = self.devices.get_mut(hardware_id) else {
+            return;
+        };
+        let criteria = LidOpenCriteria {
+            drop: self.cfg.temperatures(device_state.unit).lid_open_drop,
+            window,
+        };
+        let pit_history = pit_channel
+            .and_then(|pit_channel| device_state.channels.get(&pit_channel))
+            .map(|channel_state| &channel_state.history);
+
+        match (pit_temp, pit_history) {
+            (Some(pit_temp), Some(pit_history)) => {
+                let recent = pit_history.samples_since(now - criteria.window);
+                if device_state.lid.evaluate(pit_temp, recent, &criteria, now) {
+                    info!("lid opened on {}", hardware_id);
+                    device_state.cook.record_lid_opening();
+                }
+            }
+            _ => device_state.lid.reset(),
+        }
+        let open = device_state.lid.open();
+        let attributes = serde_json::json!({
+            "openings_this_cook": device_state.cook.current().map(|session| session.lid_openings),
+            "last_opened": device_state.lid.last_opened(),
+        });
+
+        self.publish(
+            self.get_topic_device_lid(hardware_id),
+            QoS::AtMostOnce,
+            false,
+            if open { ON } else { OFF },
+        )
+        .await;
+        self.publish(
+            self.get_topic_device_lid_attributes(hardware_id),
+            QoS::AtMostOnce,
+            false,
+            attributes.to_string(),
+        )
+        .await;
+    }
+
+    /// Feeds a battery reading into the device's battery model and publishes what it thinks
+    async fn update_battery(
+        &mut self,
+        hardware_id: &String,
+        time: DateTime<Local>,
+        batt_percentage: u8,
+    ) {
+        let low_threshold = self.cfg.battery_low_threshold;
+        let Some(device_state) = self.devices.get_mut(hardware_id) else {
+            return;
+        };
+        device_state.battery.push(time, batt_percentage as f32);
+        let charging = device_state.battery.charging();
+        let runtime = device_state.battery.runtime_remaining_hours();
+
+        self.publish(
+            self.get_topic_device_battery_runtime(hardware_id),
+            QoS::AtMostOnce,
+            false,
+            runtime
+                .map(|runtime| format!("{:.1}", runtime))
+                .unwrap_or(PAYLOAD_RESET.to_string()),
+        )
+        .await;
+        self.publish(
+            self.get_topic_device_battery_low(hardware_id),
+            QoS::AtMostOnce,
+            false,
+            if batt_percentage <= low_threshold && !charging {
+                ON
+            } else {
+                OFF
+            },
+        )
+        .await;
+        self.publish(
+            self.get_topic_device_battery_charging(hardware_id),
+            QoS::AtMostOnce,
+            false,
+            if charging { ON } else { OFF },
+        )
+        .await;
+    }
+
+    /// how long channel readings are kept, long enough for every derived sensor
+    fn history_retention(&self) -> Duration {
+        let window_secs = [
+            self.cfg.rate_window_secs,
+            self.cfg.eta_window_secs,
+            self.cfg.stall_window_secs,
+            self.cfg.lid_open_window_secs,
+        ]
+        .into_iter()
+        .chain(
+            self.cfg
+                .stats_windows
+                .iter()
+                .filter_map(|window| window.duration)
+                .map(|duration| duration.num_seconds() as u64),
+        )
+        .max()
+        .unwrap_or_default();
+        Duration::seconds(window_secs as i64)
+    }
+
+    fn channel_state(&mut self, hardware_id: &String, channel: usize) -> Option<&mut ChannelState> {
+        let history_retention = self.history_retention();
+        self.devices.get_mut(hardware_id).map(|device_state| {
+            device_state
+                .channels
+                .entry(channel)
+                .or_insert_with(|| ChannelState::new(history_retention))
+        })
+    }
+
+    /// Handles a new reading for a channel, updating everything derived from it
+    async fn update_channel(
+        &mut self,
+        hardware_id: &String,
+        channel: usize,
+        temp: f32,
+        pit_temp: Option<f32>,
+    ) {
+        let now = Local::now();
+        if let Some(channel_state) = self.channel_state(hardware_id, channel) {
+            channel_state.history.push(now, temp);
+        }
+        self.update_channel_alarms(hardware_id, channel, temp, now)
+            .await;
+        self.update_channel_rate(hardware_id, channel, now).await;
+        self.update_channel_stall(hardware_id, channel, temp, now)
+            .await;
+        self.update_channel_stats(hardware_id, channel, now).await;
+        if self.pit_channel(hardware_id) != Some(channel) {
+            self.update_channel_eta(hardware_id, channel, pit_temp, now)
+                .await;
+        }
+    }
+
+    async fn update_channel_eta(
+        &mut self,
+        hardware_id: &String,
+        channel: usize,
+        pit_temp: Option<f32>,
+        now: DateTime<Local>,
+    ) {
+        let target = self.channel_target(hardware_id, &channel);
+        let window = Duration::seconds(self.cfg.eta_window_secs as i64);
+        let Some(channel_state) = self.channel_state(hardware_id, channel) else {
+            return;
+        };
+        let estimate = target.and_then(|target| {
+            let samples: Vec<_> = channel_state
+                .history
+                .samples_since(now - window)
+                .copied()
+                .collect();
+            cook_eta::estimate(&samples, target, pit_temp, now)
+        });
+
+        self.publish(
+            self.get_topic_device_channel_eta(hardware_id, &channel),
+            QoS::AtMostOnce,
+            false,
+            estimate
+                .as_ref()
+                .map(|estimate| estimate.eta.to_rfc3339())
+                .unwrap_or(PAYLOAD_RESET.to_string()),
+        )
+        .await;
+        self.publish(
+            self.get_topic_device_channel_eta_attributes(hardware_id, &channel),
+            QoS::AtMostOnce,
+            false,
+            serde_json::to_string(&estimate).unwrap(),
+        )
+        .await;
+    }
+
+    /// Runs a new channel reading through the channel's stall detector
+    async fn update_channel_stall(
+        &mut self,
+        hardware_id: &String,
+        channel: usize,
+        temp: f32,
+        now: DateTime<Local>,
+    ) {
+        let Some(unit) = self
+            .devices
+            .get(hardware_id)
+            .map(|device_state| device_state.unit)
+        else {
+            return;
+        };
+        let temperatures = self.cfg.temperatures(unit);
+        let criteria = StallCriteria {
+            band_min: temperatures.stall_temp_min,
+            band_max: temperatures.stall_temp_max,
+            max_rate_per_hour: temperatures.stall_max_rate,
+            min_duration: Duration::seconds(self.cfg.stall_min_duration_secs as i64),
+        };
+        let window = Duration::seconds(self.cfg.stall_window_secs as i64);
+        let Some(channel_state) = self.channel_state(hardware_id, channel) else {
+            return;
+        };
+        let rate_per_hour = channel_state
+            .history
+            .rate_per_minute(window, now)
+            .map(|rate| rate * 60.0);
+        let transition = channel_state
+            .stall
+            .evaluate(temp, rate_per_hour, &criteria, now);
+        let stalled = channel_state.stall.stalled();
+        let duration = channel_state.stall.duration(now);
+
+        self.publish(
+            self.get_topic_device_channel_stall(hardware_id, &channel),
+            QoS::AtMostOnce,
+            false,
+            if stalled { ON } else { OFF },
+        )
+        .await;
+        self.publish(
+            self.get_topic_device_channel_stall_duration(hardware_id, &channel),
+            QoS::AtMostOnce,
+            false,
+            duration
+                .map(|duration| duration.num_minutes())
+                .unwrap_or_default()
+                .to_string(),
+        )
+        .await;
+
+        let event = match transition {
+            Some(StallTransition::Started { since }) => {
+                DeviceEvent::new(DeviceEventType::StallStart, now)
+                    .channel(channel)
+                    .detail("temp", temp)
+                    .detail("since", since.to_rfc3339())
+            }
+            Some(StallTransition::Ended { since, duration }) => {
+                DeviceEvent::new(DeviceEventType::StallEnd, now)
+                    .channel(channel)
+                    .detail("temp", temp)
+                    .detail("since", since.to_rfc3339())
+                    .detail("duration_secs", duration.num_seconds())
+            }
+            None => return,
+        };
+        self.publish_event(hardware_id, event).await;
+    }
+
+    async fn update_channel_stats(
+        &mut self,
+        hardware_id: &String,
+        channel: usize,
+        now: DateTime<Local>,
+    ) {
+        if self.cfg.stats_windows.is_empty() {
+            return;
+        }
+        let Some(device_state) = self.devices.get(hardware_id) else {
+            return;
+        };
+        let channel_state = device_state.channels.get(&channel);
+        let stats: ChannelStats = self
+            .cfg
+            .stats_windows
+            .iter()
+            .map(|window| {
+                let stats = match window.duration {
+                    Some(duration) => channel_state.and_then(|channel_state| {
+                        channel_stats::sample_stats(
+                            channel_state.history.samples_since(now - duration),
+                        )
+                    }),
+                    None => device_state
+                        .cook
+                        .current()
+                        .and_then(|session| session.channel_stats(channel)),
+                };
+                (window.name.clone(), stats)
+            })
+            .collect();
+
+        self.publish(
+            self.get_topic_device_channel_statistics(hardware_id, &channel),
+            QoS::AtMostOnce,
+            false,
+            serde_json::to_string(&stats).unwrap(),
+        )
+        .await;
+    }
+
+    /// Runs a channel reading through the channel's reading filter, returning why it was
+    /// rejected if it was
+    fn filter_reading(
+        &mut self,
+        hardware_id: &String,
+        channel: usize,
+        temp: f32,
+    ) -> Result<(), Rejection> {
+        let Some(unit) = self
+            .devices
+            .get(hardware_id)
+            .map(|device_state| device_state.unit)
+        else {
+            return Err(Rejection::OutOfRange);
+        };
+        let temperatures = self.cfg.temperatures(unit);
+        let criteria = ReadingCriteria {
+            min: temperatures.reading_min,
+            max: temperatures.reading_max,
+            max_jump: temperatures.reading_max_jump,
+        };
+        let Some(channel_state) = self.channel_state(hardware_id, channel) else {
+            return Err(Rejection::OutOfRange);
+        };
+        let result = channel_state.filter.check(temp, &criteria);
+        if let Err(rejection) = &result {
+            warn!(
+                "rejected reading {} on {} channel {}: {:?}",
+                temp, hardware_id, channel, rejection
+            );
+        }
+        result
+    }
+
+    /// Publishes how many readings each channel's filter has rejected
+    async fn publish_rejected_readings(&self, hardware_id: &String) {
+        let Some(device_state) = self.devices.get(hardware_id) else {
+            return;
+        };
+        let per_channel: BTreeMap<String, u64> = device_state
+            .channels
+            .iter()
+            .map(|(channel, channel_state)| {
+                (
+                    format!("channel_{}", channel),
+                    channel_state.filter.rejected(),
+                )
+            })
+            .collect();
+
+        self.publish(
+            self.get_topic_device_rejected_readings(hardware_id),
+            QoS::AtMostOnce,
+            false,
+            per_channel.values().sum::<u64>().to_string(),
+        )
+        .await;
+        self.publish(
+            self.get_topic_device_rejected_readings_attributes(hardware_id),
+            QoS::AtMostOnce,
+            false,
+            serde_json::to_string(&per_channel).unwrap(),
+        )
+        .await;
+    }
+
+    /// Forgets a channel's history once its probe is unplugged, a new probe is likely a new cook
+    fn clear_channel(&mut self, hardware_id: &String, channel: usize) {
+        if let Some(channel_state) = self.channel_state(hardware_id, channel) {
+            channel_state.history.clear();
+            channel_state.rate_per_minute = None;
+            channel_state.stall.reset();
+            channel_state.filter.reset();
+        }
+    }
+
+    async fn update_channel_rate(
+        &mut self,
+        hardware_id: &String,
+        channel: usize,
+        now: DateTime<Local>,
+    ) {
+        let window = Duration::seconds(self.cfg.rate_window_secs as i64);
+        let smoothing = self.cfg.rate_smoothing;
+        let Some(channel_state) = self.channel_state(hardware_id, channel) else {
+            return;
+        };
+        let rate = channel_state.history.rate_per_minute(window, now);
+        // exponential smoothing on top of the windowed fit, a smoothing of 1.0 disables it
+        channel_state.rate_per_minute = match (rate, channel_state.rate_per_minute) {
+            (Some(rate), Some(previous)) => Some(smoothing * rate + (1.0 - smoothing) * previous),
+            (rate, _) => rate,
+        };
+        let rate = channel_state.rate_per_minute;
+
+        for rate_unit in RATE_UNITS {
+            self.publish(
+                self.get_topic_device_channel_rate(hardware_id, &channel, rate_unit.name),
+                QoS::AtMostOnce,
+                false,
+                rate.map(|rate| format!("{:.2}", rate * rate_unit.multiplier))
+                    .unwrap_or(PAYLOAD_RESET.to_string()),
+            )
+            .await;
+        }
+    }
+
+    /// Runs a new channel reading through the channel's high and low alarms
+    async fn update_channel_alarms(
+        &mut self,
+        hardware_id: &String,
+        channel: usize,
+        temp: f32,
+        now: DateTime<Local>,
+    ) {
+        let thresholds = ALARM_KINDS.map(|kind| self.alarm_threshold(hardware_id, &channel, kind));
+        let Some(unit) = self
+            .devices
+            .get(hardware_id)
+            .map(|device_state| device_state.unit)
+        else {
+            return;
+        };
+        let hysteresis = self.cfg.temperatures(unit).alarm_hysteresis;
+        let min_duration = Duration::seconds(self.cfg.alarm_min_duration_secs as i64);
+
+        let Some(channel_state) = self.channel_state(hardware_id, channel) else {
+            return;
+        };
+        let mut alarms = vec![];
+        for (kind, threshold) in ALARM_KINDS.into_iter().zip(thresholds) {
+            let alarm = match kind {
+                AlarmKind::High => &mut channel_state.high_alarm,
+                AlarmKind::Low => &mut channel_state.low_alarm,
+            };
+            let active = alarm.evaluate(kind, temp, threshold, hysteresis, min_duration, now);
+            alarms.push((kind, active));
+        }
+
+        for (kind, active) in alarms {
+            self.publish(
+                self.get_topic_device_channel_alarm(hardware_id, &channel, kind),
+                QoS::AtMostOnce,
+                false,
+                if active { ON } else { OFF },
+            )
+            .await;
+        }
+    }
+
+    /// Handles a message received on one of the subscribed command topics
+    pub async fn handle_command(&mut self, command: MQTTCommand) {
+        let base_topic = format!("{}/", self.base_topic);
+        let Some((hardware_id, command_path)) = command
+            .topic
+            .strip_prefix(&base_topic)
+            .and_then(|device_topic| device_topic.split_once('/'))
+        else {
+            debug!("ignoring message on unexpected topic {}", command.topic);
+            return;
+        };
+        let hardware_id = hardware_id.to_string();
+        // every account's watcher sees every command
+        if !self.devices.contains_key(&hardware_id) {
+            trace!(
+                "ignoring command for {}, not one of this account's devices",
+                hardware_id
+            );
+            return;
+        }
+        let payload = String::from_utf8_lossy(&command.payload).trim().to_string();
+        debug!(
+            "received command {} for {}: {}",
+            command_path, hardware_id, payload
+        );
+
+        if let Some((channel, channel_command)) = command_path
+            .strip_prefix("channel_")
+            .and_then(|channel_path| channel_path.split_once('/'))
+        {
+            let Ok(channel) = channel.parse::<usize>() else {
+                debug!("ignoring unknown command topic {}", command.topic);
+                return;
+            };
+            match channel_command {
+                "alarm_max/set" => {
+                    self.set_alarm_threshold(&hardware_id, channel, AlarmKind::High, &payload)
+                        .await
+                }
+                "alarm_min/set" => {
+                    self.set_alarm_threshold(&hardware_id, channel, AlarmKind::Low, &payload)
+                        .await
+                }
+                "target/set" => {
+                    self.set_channel_target(&hardware_id, channel, &payload)
+                        .await
+                }
+                _ => debug!("ignoring unknown command topic {}", command.topic),
+            }
+            return;
+        }
+
+        if command_path.starts_with("drive/") && !self.cfg.fireboard_enable_drive {
+            warn!(
+                "ignoring drive command for {}, drive support is not enabled",
+                hardware_id
+            );
+            return;
+        }
+
+        match command_path {
+            "drive/setpoint/set" => self.set_drive_setpoint(&hardware_id, &payload).await,
+            "drive/mode/set" => match DriveModeType::from_str(&payload) {
+                Ok(modetype) => self.set_drive_mode(&hardware_id, modetype).await,
+                Err(_) => warn!("invalid drive mode '{}' for {}", payload, hardware_id),
+            },
+            "drive/climate_mode/set" => match DriveModeType::from_hvac_mode(&payload) {
+                Some(modetype) => self.set_drive_mode(&hardware_id, modetype).await,
+                None => warn!("invalid hvac mode '{}' for {}", payload, hardware_id),
+            },
+            _ => debug!("ignoring unknown command topic {}", command.topic),
+        }
+    }
+
+    async fn set_drive_setpoint(&mut self, hardware_id: &String, payload: &str) {
+        let Some(device) = self.devices.get(hardware_id) else {
+            warn!("received drive setpoint for unknown device {}", hardware_id);
+            return;
+        };
+        let setpoint = match payload.parse::<f32>() {
+            Ok(setpoint) => setpoint,
+            Err(e) => {
+                warn!(
+                    "invalid drive setpoint '{}' for {}: {}",
+                    payload, hardware_id, e
+                );
+                return;
+            }
+        };
+        let min = device.unit.drive_setpoint_min();
+        let max = device.unit.drive_setpoint_max();
+        if !(min..=max).contains(&setpoint) {
+            warn!(
+                "drive setpoint {} for {} is outside of the allowed range {}-{}",
+                setpoint, hardware_id, min, max
+            );
+            return;
+        }
+
+        info!("setting drive setpoint for {} to {}", hardware_id, setpoint);
+        if let Err(err) = self
+            .fb_client
+            .devices()
+            .set_drive_setpoint(
+                &device.uuid,
+                Temperature::new(setpoint, device.unit),
+                device.degreetype,
+            )
+            .await
+        {
+            error!("Error setting drive setpoint: {:?}", err);
+            return;
+        }
+
+        self.tx
+            .send(MQTTAction::Publish {
+                topic: self.get_topic_device_drive_setpoint(hardware_id),
+                qos: QoS::AtMostOnce,
+                retain: false,
+                payload: setpoint.to_string().into(),
+                props: None,
+            })
+            .await
+            .unwrap();
+        self.tx
+            .send(MQTTAction::Publish {
+                topic: self.get_topic_device_drive_target_temperature(hardware_id),
+                qos: QoS::AtMostOnce,
+                retain: false,
+                payload: setpoint.to_string().into(),
+                props: None,
+            })
+            .await
+            .unwrap();
+    }
+
+    async fn set_drive_mode(&mut self, hardware_id: &String, modetype: DriveModeType) {
+        let Some(device) = self.devices.get(hardware_id) else {
+            warn!("received drive mode for unknown device {}", hardware_id);
+            return;
+        };
 
-    pub fn get_last_will(&self) -> LastWill {
-        let topic = self.get_topic_bridge_availablility();
-        LastWill {
-            topic: topic.into(),
-            message: OFFLINE.into(),
-            qos: QoS::AtLeastOnce,
-            retain: true,
-            properties: None,
+        info!("setting drive mode for {} to {}", hardware_id, modetype);
+        if let Err(err) = self
+            .fb_client
+            .devices()
+            .set_drive_mode(&device.uuid, modetype)
+            .await
+        {
+            error!("Error setting drive mode: {:?}", err);
+            return;
         }
-    }
-
-    async fn init(&mut self) {
-        // first we will set the availability of the bridge (this service)
 
         self.tx
             .send(MQTTAction::Publish {
-                topic: self.get_topic_bridge_availablility(),
-                qos: QoS::AtLeastOnce,
-                retain: true,
-                payload: ONLINE.into(),
+                topic: self.get_topic_device_drive_mode(hardware_id),
+                qos: QoS::AtMostOnce,
+                retain: false,
+                payload: modetype.to_string().into(),
+                props: None,
+            })
+            .await
+            .unwrap();
+        self.tx
+            .send(MQTTAction::Publish {
+                topic: self.get_topic_device_drive_climate_mode(hardware_id),
+                qos: QoS::AtMostOnce,
+                retain: false,
+                payload: modetype.hvac_mode().into(),
                 props: None,
             })
             .await
             .unwrap();
     }
 
+    /// Starts a discovery entity with the settings every entity shares
+    fn entity(&self, unique_id: impl Into<String>) -> MQTTDiscoveryEntityBuilder {
+        MQTTDiscoveryEntity::builder(unique_id).has_entity_name(self.cfg.has_entity_name)
+    }
+
+    /// availability topics every entity of a device depends on
+    fn get_device_availability_topics(&self, hardware_id: &String) -> Vec<String> {
+        vec![
+            self.get_topic_bridge_availablility(),
+            self.get_topic_device_availablility(hardware_id),
+        ]
+    }
+
     async fn update_discovery(&mut self, device: &FireboardApiDevice) {
         let hardware_id = device.hardware_id.clone();
-        let connections = if let Some(device_log) = &device.device_log {
-            Some(vec![["mac".to_string(), device_log.mac_nic.clone()]])
-        } else {
-            None
-        };
+        let unit = self.output_unit(device.degreetype);
+        let connections = device
+            .device_log
+            .as_ref()
+            .map(|device_log| vec![["mac".to_string(), device_log.mac_nic.clone()]]);
         let parent_device = Some(MQTTDiscoveryDevice {
             configuration_url: Some(
                 format!("https://fireboard.io/devices/{}/edit/", device.id).to_string(),
@@ -250,238 +2216,727 @@ impl FireboardWatcher {
             model: Some(device.model.clone()),
             name: Some(device.title.clone()),
             serial_number: Some(device.hardware_id.clone()),
+            suggested_area: self
+                .cfg
+                .device_override(&device.hardware_id, &device.uuid)
+                .and_then(|device_override| device_override.suggested_area.clone()),
             sw_version: Some(device.version.clone()),
             ..MQTTDiscoveryDevice::default()
         });
+        let device_availability = self.get_device_availability_topics(&hardware_id);
 
         // set battery mqtt discovery
-        let battery_id = format!("{}_battery", hardware_id);
         let battery_discovery = MQTTDiscoverySensor {
-            unique_id: battery_id.clone(),
-            object_id: battery_id,
-            name: Some("Battery".to_string()),
-            availability: vec![
-                MQTTDiscoveryAvailabilityEntry::from(self.get_topic_bridge_availablility()),
-                MQTTDiscoveryAvailabilityEntry::from(
-                    self.get_topic_device_availablility(&hardware_id),
-                ),
-            ],
+            entity: self
+                .entity(format!("{}_battery", hardware_id))
+                .name("Battery")
+                .availability(device_availability.clone())
+                .entity_category(EntityCategory::Diagnostic)
+                .device(parent_device.clone())
+                .build(),
             device_class: Some("battery".to_string()),
-            qos: 0,
-            icon: None,
             state_topic: self.get_topic_device_battery(&hardware_id),
             unit_of_measurement: Some("%".to_string()),
-            device: parent_device.clone(),
             ..MQTTDiscoverySensor::default()
         };
-        self.tx
-            .send(MQTTAction::Publish {
-                topic: self.get_topic_device_battery_discovery(&hardware_id),
-                qos: QoS::AtMostOnce,
-                retain: true,
-                payload: battery_discovery.into(),
-                props: None,
-            })
-            .await
-            .unwrap();
+        self.publish(
+            self.get_topic_device_battery_discovery(&hardware_id),
+            QoS::AtMostOnce,
+            true,
+            battery_discovery,
+        )
+        .await;
+
+        let battery_runtime_discovery = MQTTDiscoverySensor {
+            entity: self
+                .entity(format!("{}_battery_runtime", hardware_id))
+                .name("Battery Runtime")
+                .availability(device_availability.clone())
+                .entity_category(EntityCategory::Diagnostic)
+                .icon("mdi:battery-clock")
+                .device(parent_device.clone())
+                .build(),
+            device_class: Some("duration".to_string()),
+            suggested_display_precision: Some(1),
+            state_topic: self.get_topic_device_battery_runtime(&hardware_id),
+            unit_of_measurement: Some("h".to_string()),
+            ..MQTTDiscoverySensor::default()
+        };
+        self.publish(
+            self.get_topic_device_battery_runtime_discovery(&hardware_id),
+            QoS::AtMostOnce,
+            true,
+            battery_runtime_discovery,
+        )
+        .await;
+
+        let battery_low_discovery = MQTTDiscoveryBinarySensor {
+            entity: self
+                .entity(format!("{}_battery_low", hardware_id))
+                .name("Battery Low")
+                .availability(device_availability.clone())
+                .entity_category(EntityCategory::Diagnostic)
+                .device(parent_device.clone())
+                .build(),
+            device_class: Some("battery".to_string()),
+            state_topic: self.get_topic_device_battery_low(&hardware_id),
+            payload_on: Some(ON.into()),
+            payload_off: Some(OFF.into()),
+            ..MQTTDiscoveryBinarySensor::default()
+        };
+        self.publish(
+            self.get_topic_device_battery_low_discovery(&hardware_id),
+            QoS::AtMostOnce,
+            true,
+            battery_low_discovery,
+        )
+        .await;
+
+        let battery_charging_discovery = MQTTDiscoveryBinarySensor {
+            entity: self
+                .entity(format!("{}_battery_charging", hardware_id))
+                .name("Battery Charging")
+                .availability(device_availability.clone())
+                .entity_category(EntityCategory::Diagnostic)
+                .device(parent_device.clone())
+                .build(),
+            device_class: Some("battery_charging".to_string()),
+            state_topic: self.get_topic_device_battery_charging(&hardware_id),
+            payload_on: Some(ON.into()),
+            payload_off: Some(OFF.into()),
+            ..MQTTDiscoveryBinarySensor::default()
+        };
+        self.publish(
+            self.get_topic_device_battery_charging_discovery(&hardware_id),
+            QoS::AtMostOnce,
+            true,
+            battery_charging_discovery,
+        )
+        .await;
+
+        // device triggers, matched against the events on the device's event topic
+        for event_type in DEVICE_TRIGGER_EVENTS {
+            let trigger_discovery = MQTTDiscoveryDeviceTrigger {
+                topic: self.get_topic_device_events(&hardware_id),
+                trigger_type: event_type.to_string(),
+                subtype: "device".to_string(),
+                payload: Some(event_type.to_string()),
+                value_template: Some(DEVICE_TRIGGER_VALUE_TEMPLATE.to_string()),
+                device: parent_device.clone().unwrap_or_default(),
+                ..MQTTDiscoveryDeviceTrigger::default()
+            };
+            self.publish(
+                self.get_topic_device_trigger_discovery(&hardware_id, &event_type.to_string()),
+                QoS::AtMostOnce,
+                true,
+                trigger_discovery,
+            )
+            .await;
+        }
 
-        for channel in device.channels.clone() {
-            // set channel mqtt discovery
-            let channel_id = format!("{}_channel_{}", hardware_id, channel.channel);
+        let rejected_readings_discovery = MQTTDiscoverySensor {
+            entity: self
+                .entity(format!("{}_rejected_readings", hardware_id))
+                .name("Rejected Readings")
+                .availability(vec![self.get_topic_bridge_availablility()])
+                .entity_category(EntityCategory::Diagnostic)
+                .icon("mdi:filter-remove-outline")
+                .device(parent_device.clone())
+                .build(),
+            state_class: Some("total_increasing".to_string()),
+            state_topic: self.get_topic_device_rejected_readings(&hardware_id),
+            json_attributes_topic: Some(
+                self.get_topic_device_rejected_readings_attributes(&hardware_id),
+            ),
+            ..MQTTDiscoverySensor::default()
+        };
+        self.publish(
+            self.get_topic_device_rejected_readings_discovery(&hardware_id),
+            QoS::AtMostOnce,
+            true,
+            rejected_readings_discovery,
+        )
+        .await;
+
+        let cook_discovery = MQTTDiscoverySensor {
+            entity: self
+                .entity(format!("{}_cook", hardware_id))
+                .name("Current Cook")
+                .availability(vec![self.get_topic_bridge_availablility()])
+                .icon("mdi:grill")
+                .device(parent_device.clone())
+                .build(),
+            device_class: Some("timestamp".to_string()),
+            state_class: None,
+            state_topic: self.get_topic_device_cook(&hardware_id),
+            json_attributes_topic: Some(self.get_topic_device_cook_attributes(&hardware_id)),
+            ..MQTTDiscoverySensor::default()
+        };
+        self.publish(
+            self.get_topic_device_cook_discovery(&hardware_id),
+            QoS::AtMostOnce,
+            true,
+            cook_discovery,
+        )
+        .await;
+
+        let fire_out_discovery = MQTTDiscoveryBinarySensor {
+            entity: self
+                .entity(format!("{}_fire_out", hardware_id))
+                .name("Fire Out")
+                .availability(device_availability.clone())
+                .icon("mdi:fire-off")
+                .device(parent_device.clone())
+                .build(),
+            device_class: Some("problem".to_string()),
+            state_topic: self.get_topic_device_fire_out(&hardware_id),
+            payload_on: Some(ON.into()),
+            payload_off: Some(OFF.into()),
+            ..MQTTDiscoveryBinarySensor::default()
+        };
+        self.publish(
+            self.get_topic_device_fire_out_discovery(&hardware_id),
+            QoS::AtMostOnce,
+            true,
+            fire_out_discovery,
+        )
+        .await;
+
+        let lid_discovery = MQTTDiscoveryBinarySensor {
+            entity: self
+                .entity(format!("{}_lid", hardware_id))
+                .name("Lid")
+                .availability(device_availability.clone())
+                .device(parent_device.clone())
+                .build(),
+            device_class: Some("opening".to_string()),
+            state_topic: self.get_topic_device_lid(&hardware_id),
+            json_attributes_topic: Some(self.get_topic_device_lid_attributes(&hardware_id)),
+            payload_on: Some(ON.into()),
+            payload_off: Some(OFF.into()),
+        };
+        self.publish(
+            self.get_topic_device_lid_discovery(&hardware_id),
+            QoS::AtMostOnce,
+            true,
+            lid_discovery,
+        )
+        .await;
+
+        let cook_event_discovery = MQTTDiscoveryEvent {
+            entity: self
+                .entity(format!("{}_cook_event", hardware_id))
+                .name("Cook")
+                .availability(vec![self.get_topic_bridge_availablility()])
+                .icon("mdi:grill-outline")
+                .device(parent_device.clone())
+                .build(),
+            state_topic: self.get_topic_device_cook_event(&hardware_id),
+            event_types: COOK_EVENTS
+                .iter()
+                .map(|event_type| event_type.to_string())
+                .collect(),
+            ..MQTTDiscoveryEvent::default()
+        };
+        self.publish(
+            self.get_topic_device_cook_event_discovery(&hardware_id),
+            QoS::AtMostOnce,
+            true,
+            cook_event_discovery,
+        )
+        .await;
 
+        for channel in &device.channels {
+            // set channel mqtt discovery
+            let channel_id = Self::channel_id(&hardware_id, &channel.channel);
+
+            for event_type in CHANNEL_TRIGGER_EVENTS {
+                let trigger_discovery = MQTTDiscoveryDeviceTrigger {
+                    topic: self.get_topic_device_events(&hardware_id),
+                    trigger_type: event_type.to_string(),
+                    subtype: format!("channel_{}", channel.channel),
+                    payload: Some(format!("{}:{}", event_type, channel.channel)),
+                    value_template: Some(CHANNEL_TRIGGER_VALUE_TEMPLATE.to_string()),
+                    device: parent_device.clone().unwrap_or_default(),
+                    ..MQTTDiscoveryDeviceTrigger::default()
+                };
+                self.publish(
+                    self.get_topic_device_trigger_discovery(
+                        &hardware_id,
+                        &format!("channel_{}_{}", channel.channel, event_type),
+                    ),
+                    QoS::AtMostOnce,
+                    true,
+                    trigger_discovery,
+                )
+                .await;
+            }
             let channel_topic = self.get_topic_device_channel(&hardware_id, &channel.channel);
-
+            let channel_availability = [
+                device_availability.clone(),
+                vec![self.get_topic_device_channel_availability(&hardware_id, &channel.channel)],
+            ]
+            .concat();
+
+            let mut channel_entity = self
+                .entity(channel_id.clone())
+                .name(channel.channel_label.clone())
+                .availability(channel_availability.clone())
+                .device(parent_device.clone());
+            if let Some(icon) = self
+                .cfg
+                .channel_override(&hardware_id, &device.uuid, channel.channel)
+                .and_then(|channel_override| channel_override.icon.as_ref())
+            {
+                channel_entity = channel_entity.icon(icon);
+            }
             let channel_discovery = MQTTDiscoverySensor {
-                unique_id: channel_id.clone(),
-                object_id: channel_id,
-                name: Some(channel.channel_label),
-                availability: vec![
-                    MQTTDiscoveryAvailabilityEntry::from(self.get_topic_bridge_availablility()),
-                    MQTTDiscoveryAvailabilityEntry::from(
-                        self.get_topic_device_availablility(&hardware_id),
-                    ),
-                    MQTTDiscoveryAvailabilityEntry::from(
-                        self.get_topic_device_channel_availability(&hardware_id, &channel.channel),
-                    ),
-                ],
-                suggested_unit_of_measurement: Some("°F".to_string()),
+                entity: channel_entity.build(),
                 device_class: Some("temperature".to_string()),
-                qos: 0,
-                icon: None,
                 state_topic: format!("{}/state", channel_topic),
-                unit_of_measurement: Some(device.degreetype.to_string()),
-                device: parent_device.clone(),
-                // TODO make this configurable?
-                expires_after: Some(600),
+                json_attributes_topic: (!self.cfg.stats_windows.is_empty()).then(|| {
+                    self.get_topic_device_channel_statistics(&hardware_id, &channel.channel)
+                }),
+                unit_of_measurement: Some(unit.to_string()),
+                expires_after: self.cfg.expires_after_secs,
                 ..MQTTDiscoverySensor::default()
             };
-            self.tx
-                .send(MQTTAction::Publish {
-                    topic: self.get_topic_device_channel_discovery(&hardware_id, &channel.channel),
-                    qos: QoS::AtMostOnce,
-                    retain: true,
-                    payload: channel_discovery.into(),
-                    props: None,
-                })
-                .await
-                .unwrap();
-        }
-
-        // if drive_enabled {
-        // set drive mqtt discovery
-        let drive_id = format!("{}_drive", hardware_id);
-        let drive_discovery = MQTTDiscoverySensor {
-            unique_id: drive_id.clone(),
-            object_id: drive_id.clone(),
-            name: Some("Drive".to_string()),
-            availability: vec![
-                MQTTDiscoveryAvailabilityEntry::from(self.get_topic_bridge_availablility()),
-                MQTTDiscoveryAvailabilityEntry::from(
-                    self.get_topic_device_availablility(&hardware_id),
-                ),
-                MQTTDiscoveryAvailabilityEntry::from(
-                    self.get_topic_device_drive_availability(&hardware_id),
-                ),
-            ],
-            device_class: None,
-            qos: 0,
-            icon: Some("mdi:fan".to_string()),
-            // TODO make this configurable?
-            expires_after: Some(600),
-            state_topic: self.get_topic_device_drive_state(&hardware_id),
-            unit_of_measurement: Some("%".to_string()),
-            device: parent_device.clone(),
-            json_attributes_topic: Some(self.get_topic_device_drive_attributes(&hardware_id)),
-            ..MQTTDiscoverySensor::default()
-        };
+            self.publish(
+                self.get_topic_device_channel_discovery(&hardware_id, &channel.channel),
+                QoS::AtMostOnce,
+                true,
+                channel_discovery,
+            )
+            .await;
+
+            for rate_unit in RATE_UNITS {
+                let rate_discovery = MQTTDiscoverySensor {
+                    entity: self
+                        .entity(format!("{}_rate_per_{}", channel_id, rate_unit.name))
+                        .name(format!(
+                            "{} Rate per {}",
+                            channel.channel_label, rate_unit.label
+                        ))
+                        .availability(channel_availability.clone())
+                        .icon("mdi:thermometer-chevron-up")
+                        .device(parent_device.clone())
+                        .build(),
+                    state_topic: self.get_topic_device_channel_rate(
+                        &hardware_id,
+                        &channel.channel,
+                        rate_unit.name,
+                    ),
+                    unit_of_measurement: Some(format!("{}/{}", unit, rate_unit.unit)),
+                    suggested_display_precision: Some(1),
+                    ..MQTTDiscoverySensor::default()
+                };
+                self.publish(
+                    self.get_topic_device_channel_rate_discovery(
+                        &hardware_id,
+                        &channel.channel,
+                        rate_unit.name,
+                    ),
+                    QoS::AtMostOnce,
+                    true,
+                    rate_discovery,
+                )
+                .await;
+            }
 
-        self.tx
-            .send(MQTTAction::Publish {
-                topic: self.get_topic_device_drive_discovery(&hardware_id),
-                qos: QoS::AtMostOnce,
-                retain: true,
-                payload: drive_discovery.into(),
-                props: None,
-            })
-            .await
-            .unwrap();
+            if self.cfg.stats_entities {
+                for window in &self.cfg.stats_windows {
+                    for (stat_name, stat_label) in STAT_NAMES {
+                        let statistic_discovery = MQTTDiscoverySensor {
+                            entity: self
+                                .entity(format!("{}_{}_{}", channel_id, window.name, stat_name))
+                                .name(format!(
+                                    "{} {} {}",
+                                    channel.channel_label, window.name, stat_label
+                                ))
+                                .availability(channel_availability.clone())
+                                .icon("mdi:chart-bell-curve")
+                                .device(parent_device.clone())
+                                .build(),
+                            device_class: Some("temperature".to_string()),
+                            state_topic: self.get_topic_device_channel_statistics(
+                                &hardware_id,
+                                &channel.channel,
+                            ),
+                            value_template: Some(format!(
+                                "{{{{ value_json['{0}'].{1} if value_json['{0}'] else None }}}}",
+                                window.name, stat_name
+                            )),
+                            unit_of_measurement: Some(unit.to_string()),
+                            ..MQTTDiscoverySensor::default()
+                        };
+                        self.publish(
+                            self.get_topic_device_channel_statistic_discovery(
+                                &hardware_id,
+                                &channel.channel,
+                                &window.name,
+                                stat_name,
+                            ),
+                            QoS::AtMostOnce,
+                            true,
+                            statistic_discovery,
+                        )
+                        .await;
+                    }
+                }
+            }
 
-        let drive_mode_id = format!("{}_mode", drive_id.clone());
-        let drive_mode_discovery = MQTTDiscoverySensor {
-            unique_id: drive_mode_id.clone(),
-            object_id: drive_mode_id.clone(),
-            name: Some("Drive Mode".to_string()),
-            availability: vec![
-                MQTTDiscoveryAvailabilityEntry::from(self.get_topic_bridge_availablility()),
-                MQTTDiscoveryAvailabilityEntry::from(
-                    self.get_topic_device_availablility(&hardware_id),
+            let stall_discovery = MQTTDiscoveryBinarySensor {
+                entity: self
+                    .entity(format!("{}_stall", channel_id))
+                    .name(format!("{} Stalled", channel.channel_label))
+                    .availability(channel_availability.clone())
+                    .icon("mdi:chart-line-stacked")
+                    .device(parent_device.clone())
+                    .build(),
+                state_topic: self.get_topic_device_channel_stall(&hardware_id, &channel.channel),
+                payload_on: Some(ON.into()),
+                payload_off: Some(OFF.into()),
+                ..MQTTDiscoveryBinarySensor::default()
+            };
+            self.publish(
+                self.get_topic_device_channel_stall_discovery(&hardware_id, &channel.channel),
+                QoS::AtMostOnce,
+                true,
+                stall_discovery,
+            )
+            .await;
+
+            let stall_duration_discovery = MQTTDiscoverySensor {
+                entity: self
+                    .entity(format!("{}_stall_duration", channel_id))
+                    .name(format!("{} Stall Duration", channel.channel_label))
+                    .availability(channel_availability.clone())
+                    .icon("mdi:timer-pause-outline")
+                    .device(parent_device.clone())
+                    .build(),
+                device_class: Some("duration".to_string()),
+                state_topic: self
+                    .get_topic_device_channel_stall_duration(&hardware_id, &channel.channel),
+                unit_of_measurement: Some("min".to_string()),
+                ..MQTTDiscoverySensor::default()
+            };
+            self.publish(
+                self.get_topic_device_channel_stall_duration_discovery(
+                    &hardware_id,
+                    &channel.channel,
                 ),
-                MQTTDiscoveryAvailabilityEntry::from(
-                    self.get_topic_device_drive_availability(&hardware_id),
+                QoS::AtMostOnce,
+                true,
+                stall_duration_discovery,
+            )
+            .await;
+
+            let target_discovery = MQTTDiscoveryNumber {
+                entity: self
+                    .entity(format!("{}_target", channel_id))
+                    .name(format!("{} Target", channel.channel_label))
+                    .availability(vec![self.get_topic_bridge_availablility()])
+                    .entity_category(EntityCategory::Config)
+                    .icon("mdi:thermometer-check")
+                    .device(parent_device.clone())
+                    .build(),
+                device_class: Some("temperature".to_string()),
+                command_topic: self
+                    .get_topic_device_channel_target_command(&hardware_id, &channel.channel),
+                state_topic: Some(
+                    self.get_topic_device_channel_target(&hardware_id, &channel.channel),
                 ),
-            ],
-            device_class: Some("enum".to_string()),
-            options: Some(vec![
-                "off".to_string(),
-                "manual".to_string(),
-                "auto".to_string(),
-            ]),
-            qos: 0,
-            icon: Some("mdi:fan-alert".to_string()),
-            state_class: None,
-            // icon: None,
-            state_topic: self.get_topic_device_drive_mode(&hardware_id),
-            // unit_of_measurement: Some("%".to_string()),
-            device: parent_device.clone(),
-            ..MQTTDiscoverySensor::default()
-        };
-        self.tx
-            .send(MQTTAction::Publish {
-                topic: self.get_topic_device_drivemode_discovery(&hardware_id),
-                qos: QoS::AtMostOnce,
-                retain: true,
-                payload: drive_mode_discovery.into(),
-                props: None,
-            })
-            .await
-            .unwrap();
-
-        let drive_setpoint_id = format!("{}_setpoint", drive_id.clone());
-        let drive_setpoint_discovery = MQTTDiscoverySensor {
-            unique_id: drive_setpoint_id.clone(),
-            object_id: drive_setpoint_id.clone(),
-            name: Some("Drive Setpoint".to_string()),
-            availability: vec![
-                MQTTDiscoveryAvailabilityEntry::from(self.get_topic_bridge_availablility()),
-                MQTTDiscoveryAvailabilityEntry::from(
-                    self.get_topic_device_availablility(&hardware_id),
+                min: unit.probe_min(),
+                max: unit.probe_max(),
+                step: unit.drive_setpoint_step(),
+                unit_of_measurement: Some(unit.to_string()),
+                ..MQTTDiscoveryNumber::default()
+            };
+            self.publish(
+                self.get_topic_device_channel_target_discovery(&hardware_id, &channel.channel),
+                QoS::AtMostOnce,
+                true,
+                target_discovery,
+            )
+            .await;
+
+            let eta_discovery = MQTTDiscoverySensor {
+                entity: self
+                    .entity(format!("{}_eta", channel_id))
+                    .name(format!("{} Done At", channel.channel_label))
+                    .availability(channel_availability.clone())
+                    .icon("mdi:timer-sand")
+                    .device(parent_device.clone())
+                    .build(),
+                device_class: Some("timestamp".to_string()),
+                state_class: None,
+                state_topic: self.get_topic_device_channel_eta(&hardware_id, &channel.channel),
+                json_attributes_topic: Some(
+                    self.get_topic_device_channel_eta_attributes(&hardware_id, &channel.channel),
                 ),
-                MQTTDiscoveryAvailabilityEntry::from(
-                    self.get_topic_device_drive_availability(&hardware_id),
+                ..MQTTDiscoverySensor::default()
+            };
+            self.publish(
+                self.get_topic_device_channel_eta_discovery(&hardware_id, &channel.channel),
+                QoS::AtMostOnce,
+                true,
+                eta_discovery,
+            )
+            .await;
+
+            for kind in ALARM_KINDS {
+                let threshold_discovery = MQTTDiscoveryNumber {
+                    entity: self
+                        .entity(format!("{}_{}", channel_id, kind.threshold_name()))
+                        .name(format!("{} {} Alarm", channel.channel_label, kind.label()))
+                        .availability(vec![self.get_topic_bridge_availablility()])
+                        .entity_category(EntityCategory::Config)
+                        .icon("mdi:thermometer-alert")
+                        .device(parent_device.clone())
+                        .build(),
+                    device_class: Some("temperature".to_string()),
+                    command_topic: self.get_topic_device_channel_alarm_threshold_command(
+                        &hardware_id,
+                        &channel.channel,
+                        kind,
+                    ),
+                    state_topic: Some(self.get_topic_device_channel_alarm_threshold(
+                        &hardware_id,
+                        &channel.channel,
+                        kind,
+                    )),
+                    min: unit.probe_min(),
+                    max: unit.probe_max(),
+                    step: unit.drive_setpoint_step(),
+                    unit_of_measurement: Some(unit.to_string()),
+                    ..MQTTDiscoveryNumber::default()
+                };
+                self.publish(
+                    self.get_topic_device_channel_alarm_threshold_discovery(
+                        &hardware_id,
+                        &channel.channel,
+                        kind,
+                    ),
+                    QoS::AtMostOnce,
+                    true,
+                    threshold_discovery,
+                )
+                .await;
+
+                let alarm_discovery = MQTTDiscoveryBinarySensor {
+                    entity: self
+                        .entity(format!("{}_{}", channel_id, kind.state_name()))
+                        .name(format!(
+                            "{} {} Temperature",
+                            channel.channel_label,
+                            kind.label()
+                        ))
+                        .availability(channel_availability.clone())
+                        .device(parent_device.clone())
+                        .build(),
+                    device_class: Some(kind.device_class().to_string()),
+                    state_topic: self.get_topic_device_channel_alarm(
+                        &hardware_id,
+                        &channel.channel,
+                        kind,
+                    ),
+                    payload_on: Some(ON.into()),
+                    payload_off: Some(OFF.into()),
+                    ..MQTTDiscoveryBinarySensor::default()
+                };
+                self.publish(
+                    self.get_topic_device_channel_alarm_discovery(
+                        &hardware_id,
+                        &channel.channel,
+                        kind,
+                    ),
+                    QoS::AtMostOnce,
+                    true,
+                    alarm_discovery,
+                )
+                .await;
+            }
+        }
+
+        let drive_id = format!("{}_drive", hardware_id);
+        let drive_availability = [
+            device_availability.clone(),
+            vec![self.get_topic_device_drive_availability(&hardware_id)],
+        ]
+        .concat();
+        if self.cfg.fireboard_enable_drive {
+            let drive_discovery = MQTTDiscoverySensor {
+                entity: self
+                    .entity(drive_id.clone())
+                    .name("Drive")
+                    .availability(drive_availability.clone())
+                    .entity_category(EntityCategory::Diagnostic)
+                    .icon("mdi:fan")
+                    .device(parent_device.clone())
+                    .build(),
+                expires_after: self.cfg.expires_after_secs,
+                state_topic: self.get_topic_device_drive_state(&hardware_id),
+                unit_of_measurement: Some("%".to_string()),
+                json_attributes_topic: Some(self.get_topic_device_drive_attributes(&hardware_id)),
+                ..MQTTDiscoverySensor::default()
+            };
+            self.publish(
+                self.get_topic_device_drive_discovery(&hardware_id),
+                QoS::AtMostOnce,
+                true,
+                drive_discovery,
+            )
+            .await;
+
+            let drive_mode_discovery = MQTTDiscoverySelect {
+                entity: self
+                    .entity(format!("{}_mode", drive_id))
+                    .name("Drive Mode")
+                    .availability(drive_availability.clone())
+                    .entity_category(EntityCategory::Config)
+                    .icon("mdi:fan-alert")
+                    .device(parent_device.clone())
+                    .build(),
+                options: DriveModeType::iter().map(|mode| mode.to_string()).collect(),
+                command_topic: self.get_topic_device_drive_mode_command(&hardware_id),
+                state_topic: Some(self.get_topic_device_drive_mode(&hardware_id)),
+                ..MQTTDiscoverySelect::default()
+            };
+            self.publish(
+                self.get_topic_device_drivemode_discovery(&hardware_id),
+                QoS::AtMostOnce,
+                true,
+                drive_mode_discovery,
+            )
+            .await;
+
+            let drive_setpoint_discovery = MQTTDiscoveryNumber {
+                entity: self
+                    .entity(format!("{}_setpoint", drive_id))
+                    .name("Drive Setpoint")
+                    .availability(
+                        [
+                            drive_availability.clone(),
+                            vec![self.get_topic_device_drive_setpoint_availability(&hardware_id)],
+                        ]
+                        .concat(),
+                    )
+                    .entity_category(EntityCategory::Config)
+                    .icon("mdi:thermometer-auto")
+                    .device(parent_device.clone())
+                    .build(),
+                device_class: Some("temperature".to_string()),
+                command_topic: self.get_topic_device_drive_setpoint_command(&hardware_id),
+                state_topic: Some(self.get_topic_device_drive_setpoint(&hardware_id)),
+                min: unit.drive_setpoint_min(),
+                max: unit.drive_setpoint_max(),
+                step: unit.drive_setpoint_step(),
+                unit_of_measurement: Some(unit.to_string()),
+                ..MQTTDiscoveryNumber::default()
+            };
+            self.publish(
+                self.get_topic_device_drive_setpoint_discovery(&hardware_id),
+                QoS::AtMostOnce,
+                true,
+                drive_setpoint_discovery,
+            )
+            .await;
+
+            let drive_lidpaused_discovery = MQTTDiscoveryBinarySensor {
+                entity: self
+                    .entity(format!("{}_lidpaused", drive_id))
+                    .name("Drive Lid Paused")
+                    .availability(drive_availability.clone())
+                    .entity_category(EntityCategory::Diagnostic)
+                    .device(parent_device.clone())
+                    .build(),
+                // device_class: Some("opening".to_string()),
+                state_topic: self.get_topic_device_drive_lidpaused(&hardware_id),
+                payload_on: Some(ON.into()),
+                payload_off: Some(OFF.into()),
+                ..MQTTDiscoveryBinarySensor::default()
+            };
+            self.publish(
+                self.get_topic_device_drive_lidpaused_discovery(&hardware_id),
+                QoS::AtMostOnce,
+                true,
+                drive_lidpaused_discovery,
+            )
+            .await;
+
+            let drive_climate_discovery = MQTTDiscoveryClimate {
+                entity: self
+                    .entity(format!("{}_climate", drive_id))
+                    .name("Drive Thermostat")
+                    .availability(drive_availability)
+                    .icon("mdi:grill")
+                    .device(parent_device.clone())
+                    .build(),
+                current_temperature_topic: Some(
+                    self.get_topic_device_drive_current_temperature(&hardware_id),
                 ),
-                MQTTDiscoveryAvailabilityEntry::from(
-                    self.get_topic_device_drive_setpoint_availability(&hardware_id),
+                temperature_state_topic: Some(
+                    self.get_topic_device_drive_target_temperature(&hardware_id),
                 ),
-            ],
-            icon: Some("mdi:thermometer-auto".to_string()),
-            suggested_unit_of_measurement: Some("°F".to_string()),
-            device_class: Some("temperature".to_string()),
-            qos: 0,
-            state_topic: self.get_topic_device_drive_setpoint(&hardware_id),
-            unit_of_measurement: Some(device.degreetype.to_string()),
-            device: parent_device.clone(),
-            ..MQTTDiscoverySensor::default()
-        };
-        self.tx
-            .send(MQTTAction::Publish {
-                topic: self.get_topic_device_drive_setpoint_discovery(&hardware_id),
-                qos: QoS::AtMostOnce,
-                retain: true,
-                payload: drive_setpoint_discovery.into(),
-                props: None,
-            })
-            .await
-            .unwrap();
-
-        let drive_lidpaused_id = format!("{}_lidpaused", drive_id.clone());
-        let drive_lidpaused_discovery = MQTTDiscoveryBinarySensor {
-            unique_id: drive_lidpaused_id.clone(),
-            object_id: drive_lidpaused_id.clone(),
-            name: Some("Drive Lid Paused".to_string()),
-            availability: vec![
-                MQTTDiscoveryAvailabilityEntry::from(self.get_topic_bridge_availablility()),
-                MQTTDiscoveryAvailabilityEntry::from(
-                    self.get_topic_device_availablility(&hardware_id),
+                temperature_command_topic: Some(
+                    self.get_topic_device_drive_setpoint_command(&hardware_id),
                 ),
-                MQTTDiscoveryAvailabilityEntry::from(
-                    self.get_topic_device_drive_availability(&hardware_id),
+                mode_state_topic: Some(self.get_topic_device_drive_climate_mode(&hardware_id)),
+                mode_command_topic: Some(
+                    self.get_topic_device_drive_climate_mode_command(&hardware_id),
                 ),
-            ],
-            // icon: Some("mdi:fan-alert".to_string()),
-            // device_class: Some("opening".to_string()),
-            qos: 0,
-            state_topic: self.get_topic_device_drive_lidpaused(&hardware_id),
-            device: parent_device.clone(),
-            payload_on: Some(ON.into()),
-            payload_off: Some(OFF.into()),
-            ..MQTTDiscoveryBinarySensor::default()
-        };
-        self.tx
-            .send(MQTTAction::Publish {
-                topic: self.get_topic_device_drive_lidpaused_discovery(&hardware_id),
-                qos: QoS::AtMostOnce,
-                retain: true,
-                payload: drive_lidpaused_discovery.into(),
-                props: None,
-            })
-            .await
-            .unwrap();
+                modes: DriveModeType::iter()
+                    .map(|mode| mode.hvac_mode().to_string())
+                    .collect(),
+                action_topic: Some(self.get_topic_device_drive_action(&hardware_id)),
+                min_temp: unit.drive_setpoint_min(),
+                max_temp: unit.drive_setpoint_max(),
+                temp_step: unit.drive_setpoint_step(),
+                temperature_unit: Some(unit.hvac_temperature_unit().to_string()),
+                json_attributes_topic: Some(self.get_topic_device_drive_attributes(&hardware_id)),
+                ..MQTTDiscoveryClimate::default()
+            };
+            self.publish(
+                self.get_topic_device_drive_climate_discovery(&hardware_id),
+                QoS::AtMostOnce,
+                true,
+                drive_climate_discovery,
+            )
+            .await;
+        } else if self.removed.insert(format!("{}_drive", hardware_id)) {
+            // without drive support the drive's controls would only have their commands
+            // refused, so remove any an earlier run discovered
+            self.clear_retained(self.get_topics_device_drive_discovery(&hardware_id))
+                .await;
+        }
+    }
+
+    /// Records the requests a poll made, warning when the configured interval would take
+    /// more of them than the api allows in an hour
+    fn update_poll_requests(&mut self, poll_requests: u64) {
+        if poll_requests == self.poll_requests {
+            return;
+        }
+        self.poll_requests = poll_requests;
+        let needed = poll_requests * 3600 / FIREBOARD_API_REQUESTS_PER_HOUR;
+        if self.cfg.fireboard_enable_drive && needed > self.cfg.drive_poll_interval_secs {
+            warn!(
+                "account {} makes {} requests to the fireboard api per poll, polling every {}s instead of {}s to stay under its limit of {} requests per hour, set FB2MQTT_DRIVE_POLL_INTERVAL_SECS to at least {} to silence this",
+                self.account.name(),
+                poll_requests,
+                needed,
+                self.cfg.drive_poll_interval_secs,
+                FIREBOARD_API_REQUESTS_PER_HOUR,
+                needed
+            );
+        }
     }
 
     pub async fn update(&mut self) {
         info!("checking fireboard api for updates");
         let drive_enabled = self.cfg.fireboard_enable_drive;
         let result = self.fb_client.devices().list().await;
+        let mut poll_requests = 1;
         if let Ok(returned_devices) = result {
             info!("{} devices fetched successfully", returned_devices.len());
             #[cfg(feature = "pretty_print_json_logs")]
@@ -493,12 +2948,24 @@ impl FireboardWatcher {
             trace!("devices fetched successfully: {:?}", &returned_devices);
 
             self.online_device_count = 0;
+            let mut device_count = 0;
 
             for device in returned_devices {
+                let Some(device) = self.apply_overrides(device).await else {
+                    continue;
+                };
                 let hardware_id = device.hardware_id.clone();
+                if !self.claim_device(&hardware_id) {
+                    continue;
+                }
+                device_count += 1;
 
                 debug!("found device: {:?}", hardware_id);
 
+                if !self.devices.contains_key(&hardware_id) {
+                    self.add_device(&device).await;
+                }
+
                 let latest_temps = device.latest_temps.clone();
                 let device_online = {
                     let has_latest_temps = !latest_temps.is_empty();
@@ -507,7 +2974,7 @@ impl FireboardWatcher {
                     } else if let Some(device_log) = &device.device_log {
                         let now = Local::now();
                         let diff = now - device_log.date;
-                        diff.num_minutes() < FIREBOARD_DEVICELOG_UPDATE_INTERVAL_MINUTES
+                        diff.num_seconds() < self.cfg.devicelog_update_interval_secs
                     } else {
                         false
                     }
@@ -532,6 +2999,8 @@ impl FireboardWatcher {
                 // update mqtt discovery
                 self.update_discovery(&device).await;
 
+                self.update_device_online(&hardware_id, device_online).await;
+
                 // set battery state
                 if device_online {
                     self.online_device_count += 1;
@@ -546,15 +3015,44 @@ impl FireboardWatcher {
                                 props: None,
                             })
                             .await
-                            .unwrap();                        
+                            .unwrap();
+                        self.update_battery(&hardware_id, device_log.date, batt_percentage)
+                            .await;
                     }
-
-
                 }
 
+                // filter the readings before anything else sees them, keyed by channel
+                let unit = self.output_unit(device.degreetype);
+                let pit_channel = self.pit_channel(&hardware_id);
+                let mut readings = BTreeMap::new();
+                // the lid and fire out detectors are looking for sudden pit drops, which the
+                // filter holds back as spikes until they're confirmed, so they get every pit
+                // reading in the plausible range
+                let mut raw_pit_temp = None;
+                if device_online {
+                    for channel in &device.channels {
+                        if let Some(temp) = device.channel_temperature(channel) {
+                            let temp = temp.to_unit(unit);
+                            let result =
+                                self.filter_reading(&hardware_id, channel.channel, temp.value());
+                            if pit_channel == Some(channel.channel)
+                                && result != Err(Rejection::OutOfRange)
+                            {
+                                raw_pit_temp = Some(temp.value());
+                            }
+                            if result.is_ok() {
+                                readings.insert(channel.channel, temp);
+                            }
+                        }
+                    }
+                }
+                let pit_temp = pit_channel
+                    .and_then(|pit_channel| readings.get(&pit_channel))
+                    .map(|temp| temp.value());
+                let mut channel_temps = vec![];
                 if device_online {
                     // do channel temperatures
-                    for channel in device.channels {
+                    for channel in &device.channels {
                         // let unique_id = format!("{}_{}", device.hardware_id.clone(), channel.channel);
                         let channel_topic =
                             self.get_topic_device_channel(&hardware_id, &channel.channel);
@@ -565,7 +3063,7 @@ impl FireboardWatcher {
                                 topic: format!("{}/availability", channel_topic),
                                 qos: QoS::AtLeastOnce,
                                 retain: true,
-                                payload: if channel.last_templog.is_some() {
+                                payload: if readings.contains_key(&channel.channel) {
                                     ONLINE.into()
                                 } else {
                                     OFFLINE.into()
@@ -575,20 +3073,38 @@ impl FireboardWatcher {
                             .await
                             .unwrap();
 
-                        if let Some(templog) = &channel.last_templog {
+                        self.update_channel_connected(
+                            &hardware_id,
+                            channel.channel,
+                            &channel.channel_label,
+                            channel.last_templog.is_some(),
+                        )
+                        .await;
+
+                        if let Some(temp) = readings.get(&channel.channel) {
                             // channel is online
                             self.tx
                                 .send(MQTTAction::Publish {
                                     topic: format!("{}/state", channel_topic),
                                     qos: QoS::AtMostOnce,
                                     retain: false,
-                                    payload: templog.temp.to_string().into(),
+                                    payload: temp.to_string().into(),
                                     props: None,
                                 })
                                 .await
                                 .unwrap();
-                        } else {
+
+                            self.update_channel(
+                                &hardware_id,
+                                channel.channel,
+                                temp.value(),
+                                pit_temp,
+                            )
+                            .await;
+                            channel_temps.push((channel.channel, temp.value()));
+                        } else if channel.last_templog.is_none() {
                             // channel is offline
+                            self.clear_channel(&hardware_id, channel.channel);
                             // self.tx
                             //     .send(MQTTAction::Publish {
                             //         topic: format!("{}/state", channel_topic),
@@ -601,13 +3117,28 @@ impl FireboardWatcher {
                             //     .unwrap();
                         }
                     }
+                    self.publish_rejected_readings(&hardware_id).await;
                 }
 
+                self.update_cook_session(
+                    &hardware_id,
+                    CookReading {
+                        online: device_online,
+                        pit_temp,
+                        channel_temps: &channel_temps,
+                    },
+                )
+                .await;
+                self.update_lid(&hardware_id, raw_pit_temp).await;
+
+                // (driveper, setpoint) when there is a drive
+                let mut drive_reading = None;
                 if drive_enabled {
+                    poll_requests += 1;
                     let rt_drivelog_request = self
                         .fb_client
                         .devices()
-                        .get_realtime_drivelog(device.uuid)
+                        .get_realtime_drivelog(device.uuid.clone())
                         .await;
                     if let Ok(rt_drivelog) = rt_drivelog_request {
                         if let Some(drivelog) = &rt_drivelog {
@@ -624,15 +3155,20 @@ impl FireboardWatcher {
                                 .unwrap();
 
                             debug!("drivelog: {:?}", drivelog);
-                            let modetype = if drivelog.setpoint >= 100.0 {
-                                DriveModeType::Auto
-                            } else if drivelog.driveper > 0.0 {
-                                DriveModeType::Manual
-                            } else {
-                                DriveModeType::Off
-                            };
+                            if let Some(device_state) = self.devices.get_mut(&hardware_id) {
+                                device_state.drive_tied_channel = Some(drivelog.tiedchannel);
+                            }
+                            let setpoint = drivelog.setpoint_temperature(device.degreetype);
+                            // the mode the drive reports, a manual drive can still have a
+                            // setpoint left over from the last time it was in auto
+                            let modetype = drivelog.modetype;
 
                             debug!("drivelog modetype: {:?}", modetype);
+                            let setpoint = setpoint.to_unit(unit);
+                            drive_reading = Some((
+                                drivelog.driveper,
+                                (modetype == DriveModeType::Auto).then_some(setpoint.value()),
+                            ));
 
                             let state = f32_to_u8_pct(drivelog.driveper);
                             self.tx
@@ -648,7 +3184,7 @@ impl FireboardWatcher {
 
                             let drive_attributes = DriveAttributes {
                                 modetype: modetype.to_string(),
-                                setpoint: drivelog.setpoint,
+                                setpoint: setpoint.value(),
                                 tiedchannel: drivelog.tiedchannel,
                                 lid_paused: drivelog.lidpaused,
                             };
@@ -658,7 +3194,7 @@ impl FireboardWatcher {
                                         topic: self.get_topic_device_drive_setpoint(&hardware_id),
                                         qos: QoS::AtMostOnce,
                                         retain: false,
-                                        payload: drivelog.setpoint.to_string().into(),
+                                        payload: setpoint.to_string().into(),
                                         props: None,
                                     })
                                     .await
@@ -681,7 +3217,7 @@ impl FireboardWatcher {
                                         topic: self.get_topic_device_drive_setpoint(&hardware_id),
                                         qos: QoS::AtMostOnce,
                                         retain: false,
-                                        payload: "".into(),
+                                        payload: PAYLOAD_RESET.into(),
                                         props: None,
                                     })
                                     .await
@@ -741,6 +3277,54 @@ impl FireboardWatcher {
                                 })
                                 .await
                                 .unwrap();
+
+                            // climate entity
+                            let current_temperature = readings
+                                .get(&drivelog.tiedchannel)
+                                .map(|temp| temp.to_string())
+                                .unwrap_or(PAYLOAD_RESET.to_string());
+                            self.tx
+                                .send(MQTTAction::Publish {
+                                    topic: self
+                                        .get_topic_device_drive_current_temperature(&hardware_id),
+                                    qos: QoS::AtMostOnce,
+                                    retain: false,
+                                    payload: current_temperature.into(),
+                                    props: None,
+                                })
+                                .await
+                                .unwrap();
+                            self.tx
+                                .send(MQTTAction::Publish {
+                                    topic: self
+                                        .get_topic_device_drive_target_temperature(&hardware_id),
+                                    qos: QoS::AtMostOnce,
+                                    retain: false,
+                                    payload: setpoint.to_string().into(),
+                                    props: None,
+                                })
+                                .await
+                                .unwrap();
+                            self.tx
+                                .send(MQTTAction::Publish {
+                                    topic: self.get_topic_device_drive_climate_mode(&hardware_id),
+                                    qos: QoS::AtMostOnce,
+                                    retain: false,
+                                    payload: modetype.hvac_mode().into(),
+                                    props: None,
+                                })
+                                .await
+                                .unwrap();
+                            self.tx
+                                .send(MQTTAction::Publish {
+                                    topic: self.get_topic_device_drive_action(&hardware_id),
+                                    qos: QoS::AtMostOnce,
+                                    retain: false,
+                                    payload: modetype.hvac_action(drivelog.driveper).into(),
+                                    props: None,
+                                })
+                                .await
+                                .unwrap();
                         } else {
                             // drive not available
                             self.tx
@@ -791,9 +3375,58 @@ impl FireboardWatcher {
                         .await
                         .unwrap();
                 }
+
+                self.update_fire_out(&hardware_id, raw_pit_temp, drive_reading)
+                    .await;
             }
+            self.publish_account_status(AccountStatus::ok(
+                &self.account,
+                device_count,
+                self.online_device_count,
+            ))
+            .await;
+            self.update_poll_requests(poll_requests);
         } else if let Err(err) = result {
-            error!("Error fetching devices: {:?}", err);
+            error!(
+                "Error fetching devices for account {}: {:?}",
+                self.account.name(),
+                err
+            );
+            self.publish_account_status(AccountStatus::error(&self.account, err))
+                .await;
+        }
+    }
+
+    async fn publish_account_status(&self, status: AccountStatus) {
+        self.publish(
+            self.cfg.account_status_topic(&self.account),
+            QoS::AtLeastOnce,
+            true,
+            status,
+        )
+        .await;
+    }
+
+    /// Claims a device for this account, false if another account already publishes it
+    fn claim_device(&mut self, hardware_id: &String) -> bool {
+        let mut device_claims = self.device_claims.lock().unwrap();
+        let owner = device_claims
+            .entry(hardware_id.clone())
+            .or_insert_with(|| self.account.name().to_string());
+        if owner != self.account.name() {
+            // only warn the first time it turns up
+            if self.shared.insert(hardware_id.clone()) {
+                warn!(
+                    "device {} is on accounts {} and {}, only publishing it for {}",
+                    hardware_id,
+                    owner,
+                    self.account.name(),
+                    owner
+                );
+            }
+            return false;
         }
+        self.shared.remove(hardware_id);
+        true
     }
 }
@@ -0,0 +1,269 @@
+//! Running as a home assistant add-on: the add-on's options and the mqtt broker the
+//! Supervisor provides, loaded as the lowest config layer.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::constants::USER_AGENT;
+
+/// Where the Supervisor writes the options set on the add-on's configuration page
+const OPTIONS_PATH_DEFAULT: &str = "/data/options.json";
+/// The add-on's persistent storage
+const STATE_DIR: &str = "/data";
+const SUPERVISOR_URL_DEFAULT: &str = "http://supervisor";
+
+/// The add-on's options file, if we're running as an add-on. `FB2MQTT_ADDON_OPTIONS_PATH`
+/// changes where it's looked for.
+fn options_path() -> Option<PathBuf> {
+    let path = std::env::var("FB2MQTT_ADDON_OPTIONS_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(OPTIONS_PATH_DEFAULT));
+    path.is_file().then_some(path)
+}
+
+/// Reads the options file into config fields. Options can be named after the field
+/// (`mqtt_url`) or the env var (`FB2MQTT_MQTT_URL`), and options left empty in the add-on
+/// UI are dropped so they don't hide the defaults.
+fn read_options(path: &Path) -> Result<Map<String, Value>> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("can't read {}", path.display()))?;
+    let options: Map<String, Value> = serde_json::from_str(&contents)
+        .with_context(|| format!("{} isn't a json object", path.display()))?;
+    Ok(options
+        .into_iter()
+        .filter(|(_, value)| !value.is_null() && value.as_str() != Some(""))
+        .map(|(key, value)| {
+            let key = key.to_lowercase();
+            let key = key
+                .strip_prefix("fb2mqtt_")
+                .map(str::to_string)
+                .unwrap_or(key);
+            (key, value)
+        })
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+struct SupervisorResponse<T> {
+    result: String,
+    data: Option<T>,
+    message: Option<String>,
+}
+
+/// The mqtt broker the Supervisor provides to add-ons, e.g. the Mosquitto add-on
+#[derive(Debug, Deserialize)]
+struct MqttService {
+    host: String,
+    port: u16,
+    #[serde(default)]
+    ssl: bool,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+async fn fetch_mqtt_service(supervisor_url: &str, token: &str) -> Result<MqttService> {
+    let url = format!("{}/services/mqtt", supervisor_url.trim_end_matches('/'));
+    debug!("fetching mqtt service from {}", url);
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?;
+    let response = client
+        .get(&url)
+        .header("User-Agent", USER_AGENT)
+        .bearer_auth(token)
+        .send()
+        .await
+        .with_context(|| format!("can't reach the supervisor at {}", url))?;
+    let status = response.status();
+    // the supervisor answers errors with a message in the same envelope
+    let response: SupervisorResponse<MqttService> = response
+        .json()
+        .await
+        .with_context(|| format!("unexpected response from {} ({})", url, status))?;
+    match response.data {
+        Some(service) if response.result == "ok" => Ok(service),
+        _ => Err(anyhow!(
+            "{} ({})",
+            response
+                .message
+                .unwrap_or_else(|| "no mqtt service available".to_string()),
+            status
+        )),
+    }
+}
+
+/// The add-on layer: `/data` as the state dir, the Supervisor's mqtt broker unless the
+/// options set `mqtt_url`, and the add-on's options on top. `None` when we aren't running
+/// as an add-on.
+pub async fn addon_layer() -> Result<Option<Value>> {
+    let Some(path) = options_path() else {
+        return Ok(None);
+    };
+    let token = std::env::var("SUPERVISOR_TOKEN").ok();
+    let supervisor_url = std::env::var("FB2MQTT_SUPERVISOR_URL")
+        .unwrap_or_else(|_| SUPERVISOR_URL_DEFAULT.to_string());
+    build_layer(&path, token.as_deref(), &supervisor_url)
+        .await
+        .map(Some)
+}
+
+async fn build_layer(path: &Path, token: Option<&str>, supervisor_url: &str) -> Result<Value> {
+    info!(
+        "running as a home assistant add-on, loading {}",
+        path.display()
+    );
+    let options = read_options(path)?;
+
+    let mut layer = Map::new();
+    layer.insert("state_dir".to_string(), STATE_DIR.into());
+
+    match token {
+        Some(token) if !options.contains_key("mqtt_url") => {
+            match fetch_mqtt_service(supervisor_url, token).await {
+                // we can't speak tls, and connecting in plaintext to a tls port would only
+                // fail in confusing ways, so leave the broker for the user to set
+                Ok(service) if service.ssl => warn!(
+                    "the supervisor's mqtt broker at {}:{} wants ssl, which isn't supported, set mqtt_url in the add-on options to a broker without ssl",
+                    service.host, service.port
+                ),
+                Ok(service) => {
+                    info!(
+                        "using the mqtt broker at {}:{} from the supervisor",
+                        service.host, service.port
+                    );
+                    layer.insert(
+                        "mqtt_url".to_string(),
+                        format!("mqtt://{}:{}", service.host, service.port).into(),
+                    );
+                    if let Some(username) = service.username {
+                        layer.insert("mqtt_username".to_string(), username.into());
+                    }
+                    if let Some(password) = service.password {
+                        layer.insert("mqtt_password".to_string(), password.into());
+                    }
+                }
+                Err(err) => warn!(
+                    "couldn't get the mqtt broker from the supervisor, set mqtt_url in the add-on options: {:#}",
+                    err
+                ),
+            }
+        }
+        Some(_) => debug!("mqtt_url set in the add-on options, not asking the supervisor"),
+        None => debug!("no SUPERVISOR_TOKEN, not asking the supervisor for an mqtt broker"),
+    }
+
+    layer.extend(options);
+    Ok(Value::Object(layer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves `body` as json to the first request, standing in for the supervisor. Returns
+    /// its url.
+    async fn supervisor(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 4096];
+            let _ = socket.read(&mut request).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    /// A url nothing is listening on
+    async fn unreachable_supervisor() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        format!("http://{}", addr)
+    }
+
+    fn options_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "fb2mqtt-addon-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    const MQTT_SERVICE: &str = r#"{"result":"ok","data":{"host":"core-mosquitto","port":1883,"ssl":false,"username":"addons","password":"secret","protocol":"3.1.1"}}"#;
+
+    #[tokio::test]
+    async fn uses_the_supervisors_broker() {
+        let url = supervisor(MQTT_SERVICE).await;
+        let path = options_file(
+            "success",
+            r#"{"fireboardaccount_email":"a@b.c","mqtt_username":""}"#,
+        );
+        let layer = build_layer(&path, Some("token"), &url).await.unwrap();
+        assert_eq!(layer["mqtt_url"], "mqtt://core-mosquitto:1883");
+        assert_eq!(layer["mqtt_username"], "addons");
+        assert_eq!(layer["mqtt_password"], "secret");
+        assert_eq!(layer["state_dir"], STATE_DIR);
+        assert_eq!(layer["fireboardaccount_email"], "a@b.c");
+    }
+
+    #[tokio::test]
+    async fn leaves_a_tls_broker_alone() {
+        let url = supervisor(
+            r#"{"result":"ok","data":{"host":"core-mosquitto","port":8883,"ssl":true}}"#,
+        )
+        .await;
+        let path = options_file("ssl", "{}");
+        let layer = build_layer(&path, Some("token"), &url).await.unwrap();
+        assert!(layer.get("mqtt_url").is_none());
+    }
+
+    #[tokio::test]
+    async fn supervisor_unreachable() {
+        let url = unreachable_supervisor().await;
+        assert!(fetch_mqtt_service(&url, "token").await.is_err());
+
+        let path = options_file("unreachable", r#"{"fireboardaccount_email":"a@b.c"}"#);
+        let layer = build_layer(&path, Some("token"), &url).await.unwrap();
+        assert!(layer.get("mqtt_url").is_none());
+        assert_eq!(layer["fireboardaccount_email"], "a@b.c");
+    }
+
+    #[tokio::test]
+    async fn supervisor_error() {
+        let url = supervisor(r#"{"result":"error","message":"Service not enabled"}"#).await;
+        let err = fetch_mqtt_service(&url, "token").await.unwrap_err();
+        assert!(err.to_string().contains("Service not enabled"));
+    }
+
+    #[tokio::test]
+    async fn missing_token() {
+        // the supervisor would hand out a broker, but without a token it isn't asked
+        let url = supervisor(MQTT_SERVICE).await;
+        let path = options_file("no-token", "{}");
+        let layer = build_layer(&path, None, &url).await.unwrap();
+        assert!(layer.get("mqtt_url").is_none());
+        assert!(layer.get("mqtt_username").is_none());
+
+        let path = options_file(
+            "no-token-url",
+            r#"{"FB2MQTT_MQTT_URL":"mqtt://broker:1884"}"#,
+        );
+        let layer = build_layer(&path, None, &url).await.unwrap();
+        assert_eq!(layer["mqtt_url"], "mqtt://broker:1884");
+    }
+}
@@ -0,0 +1,202 @@
+//! # Lid Open Detection
+//!
+//! Opening the lid dumps the hot air out of the pit, so the pit channel drops sharply
+//! within a poll or two and then climbs back once the lid is shut. This gives plain
+//! Fireboard users (without a Drive and its `lidpaused`) a lid sensor.
+use chrono::{DateTime, Duration, Local};
+
+use crate::channel_history::TempSample;
+
+/// how much of the drop the pit has to climb back, from its lowest point, before the lid
+/// is considered shut again
+const LID_CLOSE_RECOVERY_FRACTION: f32 = 0.25;
+
+/// a lid that looks open for longer than this is more likely a fire dying down, give up on it
+const LID_OPEN_MAX_DURATION_MINUTES: i64 = 15;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LidOpenCriteria {
+    /// the drop, in degrees, that counts as the lid opening
+    pub drop: f32,
+    /// how quickly the drop has to happen
+    pub window: Duration,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct LidOpenDetector {
+    opened: Option<DateTime<Local>>,
+    lowest: f32,
+    last_opened: Option<DateTime<Local>>,
+    /// when the lid last shut. The pit is still below where it was before the lid opened,
+    /// so only readings since then count towards the next drop
+    closed: Option<DateTime<Local>>,
+}
+
+impl LidOpenDetector {
+    /// Feeds the pit channel's latest reading into the detector, with the pit's readings
+    /// over the criteria's window. Returns true when the lid has just opened.
+    pub fn evaluate<'a>(
+        &mut self,
+        pit_temp: f32,
+        recent: impl IntoIterator<Item = &'a TempSample>,
+        criteria: &LidOpenCriteria,
+        now: DateTime<Local>,
+    ) -> bool {
+        if let Some(opened) = self.opened {
+            self.lowest = self.lowest.min(pit_temp);
+            let recovered = pit_temp >= self.lowest + criteria.drop * LID_CLOSE_RECOVERY_FRACTION;
+            if recovered || now - opened > Duration::minutes(LID_OPEN_MAX_DURATION_MINUTES) {
+                self.opened = None;
+                self.closed = Some(now);
+            }
+            return false;
+        }
+
+        let recent_max = recent
+            .into_iter()
+            .filter(|sample| now - sample.time <= criteria.window)
+            .filter(|sample| self.closed.is_none_or(|closed| sample.time > closed))
+            .map(|sample| sample.temp)
+            .fold(pit_temp, f32::max);
+        if recent_max - pit_temp >= criteria.drop {
+            self.opened = Some(now);
+            self.last_opened = Some(now);
+            self.lowest = pit_temp;
+            return true;
+        }
+        false
+    }
+
+    pub fn open(&self) -> bool {
+        self.opened.is_some()
+    }
+
+    pub fn last_opened(&self) -> Option<DateTime<Local>> {
+        self.last_opened
+    }
+
+    /// forgets an open lid, e.g. when the pit probe is unplugged
+    pub fn reset(&mut self) {
+        self.opened = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel_history::ChannelHistory;
+    use crate::reading_filter::{ReadingCriteria, ReadingFilter, Rejection};
+
+    const CRITERIA: LidOpenCriteria = LidOpenCriteria {
+        drop: 20.0,
+        window: Duration::minutes(2),
+    };
+
+    /// Feeds readings 30 seconds apart through a history like the watcher does, returning
+    /// how many times the lid opened
+    fn openings(readings: &[f32]) -> usize {
+        let start = Local::now();
+        let mut history = ChannelHistory::new(Duration::minutes(10));
+        let mut detector = LidOpenDetector::default();
+        let mut openings = 0;
+        for (i, &temp) in readings.iter().enumerate() {
+            let now = start + Duration::seconds(30 * i as i64);
+            history.push(now, temp);
+            let recent = history.samples_since(now - CRITERIA.window);
+            if detector.evaluate(temp, recent, &CRITERIA, now) {
+                openings += 1;
+            }
+        }
+        openings
+    }
+
+    #[test]
+    fn steady_pit_never_opens() {
+        assert_eq!(openings(&[250.0; 10]), 0);
+    }
+
+    #[test]
+    fn slow_drop_never_opens() {
+        let readings: Vec<f32> = (0..20).map(|i| 250.0 - 2.5 * i as f32).collect();
+        assert_eq!(openings(&readings), 0);
+    }
+
+    #[test]
+    fn partial_recovery_counts_once() {
+        // drops 30, climbs back a quarter of the way and sits there below the old peak
+        let readings = [
+            250.0, 250.0, 220.0, 221.0, 228.0, 228.0, 229.0, 229.0, 230.0,
+        ];
+        assert_eq!(openings(&readings), 1);
+    }
+
+    #[test]
+    fn second_opening_after_close_counts() {
+        let readings = [
+            250.0, 250.0, 220.0, 230.0, 240.0, 250.0, 250.0, 225.0, 240.0,
+        ];
+        assert_eq!(openings(&readings), 2);
+    }
+
+    /// Like `openings`, but through a reading filter first the way the watcher does: only
+    /// accepted readings make it into the history, and the detector sees every reading
+    /// that isn't out of range
+    fn filtered_openings(readings: &[f32]) -> usize {
+        let criteria = ReadingCriteria {
+            min: -40.0,
+            max: 1000.0,
+            max_jump: 50.0,
+        };
+        let start = Local::now();
+        let mut filter = ReadingFilter::default();
+        let mut history = ChannelHistory::new(Duration::minutes(10));
+        let mut detector = LidOpenDetector::default();
+        let mut openings = 0;
+        for (i, &temp) in readings.iter().enumerate() {
+            let now = start + Duration::seconds(30 * i as i64);
+            let result = filter.check(temp, &criteria);
+            if result.is_ok() {
+                history.push(now, temp);
+            }
+            if result == Err(Rejection::OutOfRange) {
+                detector.reset();
+                continue;
+            }
+            let recent = history.samples_since(now - CRITERIA.window);
+            if detector.evaluate(temp, recent, &CRITERIA, now) {
+                openings += 1;
+            }
+        }
+        openings
+    }
+
+    #[test]
+    fn drop_bigger_than_the_spike_filter_allows() {
+        // a 70 degree drop is held back by the filter as a spike, but still opens the lid
+        let readings = [250.0, 250.0, 250.0, 250.0, 180.0, 200.0, 235.0, 250.0];
+        assert_eq!(filtered_openings(&readings), 1);
+    }
+
+    #[test]
+    fn out_of_range_readings_never_open() {
+        let readings = [250.0, 250.0, 250.0, -100.0, 250.0, 250.0];
+        assert_eq!(filtered_openings(&readings), 0);
+    }
+
+    #[test]
+    fn lid_closes_after_recovery() {
+        let start = Local::now();
+        let mut detector = LidOpenDetector::default();
+        let sample = |secs: i64, temp: f32| TempSample {
+            time: start + Duration::seconds(secs),
+            temp,
+        };
+        let history = [sample(0, 250.0), sample(30, 220.0)];
+        assert!(detector.evaluate(220.0, &history, &CRITERIA, history[1].time));
+        assert!(detector.open());
+        assert!(!detector.evaluate(224.0, &history, &CRITERIA, start + Duration::seconds(60)));
+        assert!(detector.open());
+        assert!(!detector.evaluate(226.0, &history, &CRITERIA, start + Duration::seconds(90)));
+        assert!(!detector.open());
+    }
+}
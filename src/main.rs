@@ -1,25 +1,56 @@
 use crate::{
-    config::load_cfg_from_env, fireboard_watcher::FireboardWatcher, mqtt_action::MQTTAction,
+    account_status::AccountStatus,
+    config::{build_cfg, check_cfg, cli_command, load_cfg, Fb2MqttConfig, FireboardAccount},
+    constants::{ACCOUNT_RETRY_MAX_SECS, ACCOUNT_RETRY_MIN_SECS},
+    fireboard_watcher::{DeviceClaims, FireboardWatcher},
+    mqtt_action::{MQTTAction, MQTTCommand},
 };
 use env_logger::{Builder, Env};
 use human_bytes::human_bytes;
 use log::{debug, error, info, trace, warn};
 use memory_stats::memory_stats;
-use rumqttc::v5::{AsyncClient, MqttOptions};
+use rumqttc::v5::{
+    mqttbytes::{
+        v5::{LastWill, Packet},
+        QoS,
+    },
+    AsyncClient, Event, MqttOptions,
+};
 use std::process;
 use tokio::{
-    sync::mpsc,
-    time::{self, sleep},
+    signal::unix::{signal, SignalKind},
+    sync::{
+        mpsc::{self, error::TrySendError},
+        watch,
+    },
+    time::{self, sleep_until},
 };
 
 
+mod account_status;
+mod alarm;
+mod battery;
+mod channel_history;
+mod channel_stats;
 mod config;
 mod constants;
+mod cook_eta;
+mod cook_session;
 mod device;
+mod device_event;
+mod device_state;
 mod drive;
 mod fireboard_api;
+mod fire_out;
 mod fireboard_watcher;
+mod ha_addon;
+mod lid_open;
 mod mqtt_action;
+mod reading_filter;
+mod stall;
+mod state_store;
+mod temperature;
+mod token_store;
 mod utils;
 
 
@@ -30,7 +61,13 @@ async fn main() {
     builder.init();
 
     
-    let cfg = load_cfg_from_env();
+    let args = cli_command().get_matches();
+    if args.subcommand_matches("check-config").is_some() {
+        process::exit(check_cfg(&args).await);
+    }
+    let mut cfg = load_cfg(&args).await;
+    // registered straight away, SIGHUP would stop the process until then
+    let mut sighup = signal(SignalKind::hangup()).unwrap();
 
     
 
@@ -38,29 +75,27 @@ async fn main() {
     
 
     let (tx_mqtt, mut rx_mqtt) = mpsc::channel::<MQTTAction>(16);
-    let mut watcher = {
-        let watcher_result = FireboardWatcher::new(&cfg, tx_mqtt.clone()).await;
-        if let Err(e) = watcher_result {
-            error!("Error setting up FireboardWatcher: {:?}", e);
-            process::exit(2);
-        }
-        watcher_result.unwrap()
-    };
-
-    let (mqtt_client, mut mqtt_eventloop) = {
-        let cfg = cfg.clone();
-        info!("connecting to mqtt broker at {}:{} with clientId {}", cfg.mqtt_host, cfg.mqtt_port, cfg.mqtt_clientid);
-        let mut mqtt_options = MqttOptions::new(
-            cfg.mqtt_clientid.clone(),
-            cfg.mqtt_host.clone(),
-            cfg.mqtt_port,
-        );
-        if let Some(mqtt_credentials) = cfg.mqtt_credentials {
-            mqtt_options.set_credentials(mqtt_credentials.username, mqtt_credentials.password);
+    // one watcher per account, each with its own api client and rate budget
+    let device_claims = DeviceClaims::default();
+    let mut watchers = vec![];
+    let mut failed_accounts = vec![];
+    for account in &cfg.accounts {
+        match FireboardWatcher::new(&cfg, account, device_claims.clone(), tx_mqtt.clone()).await {
+            Ok(watcher) => watchers.push(watcher),
+            Err(e) => {
+                error!("Error setting up FireboardWatcher for account {}: {:?}", account.name(), e);
+                failed_accounts.push((account, e));
+            }
         }
-        mqtt_options.set_last_will(watcher.get_last_will());
-        AsyncClient::new(mqtt_options, 16)
-    };
+    }
+    if watchers.is_empty() {
+        process::exit(2);
+    }
+
+    // every account shares the bridge's availability topic
+    let last_will = watchers[0].get_last_will();
+    let (mqtt_client, mut mqtt_eventloop) =
+        AsyncClient::new(mqtt_options(&cfg, last_will.clone()), 16);
 
     tokio::spawn(async move {
         while let Some(action) = rx_mqtt.recv().await {
@@ -115,55 +150,232 @@ async fn main() {
     });
     // watcher.init().await;
 
+    // every watcher gets every command, and ignores the ones for other accounts' devices
+    let (tx_reload, rx_reload) = watch::channel(Reload {
+        cfg: cfg.clone(),
+        reconnected: false,
+    });
+    let mut tx_commands = vec![];
+    for watcher in watchers {
+        let (tx_command, rx_command) = mpsc::channel::<MQTTCommand>(16);
+        tx_commands.push(tx_command);
+        tokio::spawn(poll_account(watcher, rx_reload.clone(), rx_command));
+    }
+    // accounts that couldn't start (e.g. the fireboard api was briefly down) keep trying
+    for (account, e) in failed_accounts {
+        let (tx_command, rx_command) = mpsc::channel::<MQTTCommand>(16);
+        tx_commands.push(tx_command);
+        tokio::spawn(retry_account(
+            account.clone(),
+            e,
+            device_claims.clone(),
+            tx_mqtt.clone(),
+            rx_reload.clone(),
+            rx_command,
+        ));
+    }
+
+    // re-read and validate the config on SIGHUP, keeping the current one if it's invalid
+    let (tx_cfg, mut rx_cfg) = mpsc::channel::<Fb2MqttConfig>(1);
     tokio::spawn(async move {
-        loop {
-            watcher.update().await;
-            if let Some(usage) = memory_stats() {
-                info!(
-                    "Current physical memory usage: {}",
-                    human_bytes(usage.physical_mem as u32)
-                );
-                // info!("Current virtual memory usage: {}", usage.virtual_mem);
-            }
-            debug!("there are {} devices online", watcher.online_device_count());
-            let sleep_duration = if watcher.online_device_count() > 0 {
-                // the fireboard cloud api has a rate limit of 200 requests per hour
-                // which works out to 1 request every 18 seconds, or 1 every 20 secs to be safe,
-                // so we need to be careful about how often we poll for updates
-                let default_base_interval = 20;
-                if cfg.fireboard_enable_drive {
-                    debug!("drive support is enabled");
-                    // if drive is enabled, then for each online device we need to make
-                    // one extra call to the fireboard cloud api
-                    default_base_interval * 2
-                } else {
-                    debug!("drive support not enabled");
-                    // if drive is not enabled, then we only need to make one call total
-                    // when we call `update()` to get the temps for all devices
-                    default_base_interval
+        while sighup.recv().await.is_some() {
+            info!("SIGHUP received, reloading config");
+            match build_cfg(&args).await {
+                Ok(cfg) => tx_cfg.send(cfg).await.unwrap(),
+                Err(errors) => {
+                    for err in &errors {
+                        error!("{}", err);
+                    }
+                    error!("config is invalid ({} errors), keeping the current config", errors.len());
                 }
-            } else {
-                // we default to polling once a minute when no devices are online
-                60
-            };
-            debug!(
-                "updating from fireboard cloud api in {} seconds",
-                sleep_duration
-            );
-            sleep(time::Duration::from_secs(sleep_duration)).await;
+            }
         }
     });
 
+    // the broker and config from before a reload moved us to a new broker, to go back to if
+    // we can't connect to it
+    let mut previous_broker: Option<(MqttOptions, Fb2MqttConfig)> = None;
+
 
     loop {
-        let event = mqtt_eventloop.poll().await;
-        match &event {
-            Ok(v) => {
-                trace!("mqtt event: {v:?}");
+        tokio::select! {
+            event = mqtt_eventloop.poll() => match &event {
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    trace!("mqtt incoming publish: {publish:?}");
+                    let command = MQTTCommand {
+                        topic: String::from_utf8_lossy(&publish.topic).to_string(),
+                        payload: publish.payload.clone(),
+                    };
+                    // never wait on a watcher here, it may be waiting on us to publish. A
+                    // watcher too busy to keep up loses the command instead
+                    for tx_command in &tx_commands {
+                        match tx_command.try_send(command.clone()) {
+                            Ok(()) => {}
+                            Err(TrySendError::Full(command)) => {
+                                warn!("dropping mqtt command for {}, an account is busy", command.topic);
+                            }
+                            Err(e) => error!("unable to forward mqtt command: {e:?}"),
+                        }
+                    }
+                }
+                Ok(Event::Incoming(Packet::ConnAck(_))) if previous_broker.is_some() => {
+                    info!("connected to mqtt broker at {}:{}", cfg.mqtt_host, cfg.mqtt_port);
+                    previous_broker = None;
+                }
+                Ok(v) => {
+                    trace!("mqtt event: {v:?}");
+                }
+                Err(e) => {
+                    let Some((options, previous_cfg)) = previous_broker.take() else {
+                        error!("mqtt error: {e:?}");
+                        process::exit(3);
+                    };
+                    error!(
+                        "unable to connect to mqtt broker at {}:{}, going back to {}:{}: {e:?}",
+                        cfg.mqtt_host, cfg.mqtt_port, previous_cfg.mqtt_host, previous_cfg.mqtt_port
+                    );
+                    mqtt_eventloop.options = options;
+                    cfg.mqtt_host = previous_cfg.mqtt_host;
+                    cfg.mqtt_port = previous_cfg.mqtt_port;
+                    cfg.mqtt_clientid = previous_cfg.mqtt_clientid;
+                    cfg.mqtt_credentials = previous_cfg.mqtt_credentials;
+                    // the old broker's session was dropped as well
+                    tx_reload.send_replace(Reload {
+                        cfg: cfg.clone(),
+                        reconnected: true,
+                    });
+                }
+            },
+            Some(new_cfg) = rx_cfg.recv() => {
+                let new_cfg = cfg.reloaded(new_cfg);
+                let reconnect = cfg.needs_reconnect(&new_cfg);
+                if reconnect {
+                    info!("mqtt settings changed, reconnecting to {}:{}", new_cfg.mqtt_host, new_cfg.mqtt_port);
+                    let options = mqtt_options(&new_cfg, last_will.clone());
+                    let previous_options = std::mem::replace(&mut mqtt_eventloop.options, options);
+                    previous_broker = Some((previous_options, cfg.clone()));
+                    // drops the connection, unsent messages go out on the new one
+                    mqtt_eventloop.clean();
+                }
+                cfg = new_cfg;
+                debug!("config reloaded: {}", serde_json::to_string_pretty(&cfg).unwrap());
+                tx_reload.send_replace(Reload {
+                    cfg: cfg.clone(),
+                    reconnected: reconnect,
+                });
+                info!("config reloaded");
+            }
+        }
+    }
+}
+
+/// A config re-read on SIGHUP, handed to every account's poll loop
+#[derive(Clone)]
+struct Reload {
+    cfg: Fb2MqttConfig,
+    /// the mqtt connection was replaced, so subscriptions have to be made again
+    reconnected: bool,
+}
+
+fn mqtt_options(cfg: &Fb2MqttConfig, last_will: LastWill) -> MqttOptions {
+    info!("connecting to mqtt broker at {}:{} with clientId {}", cfg.mqtt_host, cfg.mqtt_port, cfg.mqtt_clientid);
+    let mut mqtt_options = MqttOptions::new(
+        cfg.mqtt_clientid.clone(),
+        cfg.mqtt_host.clone(),
+        cfg.mqtt_port,
+    );
+    if let Some(mqtt_credentials) = cfg.mqtt_credentials.clone() {
+        mqtt_options.set_credentials(mqtt_credentials.username, mqtt_credentials.password);
+    }
+    mqtt_options.set_last_will(last_will);
+    mqtt_options
+}
+
+/// Keeps trying to start an account that couldn't start, backing off between attempts and
+/// publishing its status after each failure, then polls it like the others
+async fn retry_account(
+    account: FireboardAccount,
+    mut error: anyhow::Error,
+    device_claims: DeviceClaims,
+    tx_mqtt: mpsc::Sender<MQTTAction>,
+    mut rx_reload: watch::Receiver<Reload>,
+    mut rx_command: mpsc::Receiver<MQTTCommand>,
+) {
+    let mut backoff = ACCOUNT_RETRY_MIN_SECS;
+    loop {
+        let cfg = rx_reload.borrow_and_update().cfg.clone();
+        tx_mqtt
+            .send(MQTTAction::Publish {
+                topic: cfg.account_status_topic(&account),
+                qos: QoS::AtLeastOnce,
+                retain: true,
+                payload: AccountStatus::error(&account, &error).into(),
+                props: None,
+            })
+            .await
+            .unwrap();
+        info!("retrying account {} in {} seconds", account.name(), backoff);
+        let retry_at = time::Instant::now() + time::Duration::from_secs(backoff);
+        loop {
+            tokio::select! {
+                _ = sleep_until(retry_at) => break,
+                // the account has no devices yet, so none of the commands are for it
+                Some(command) = rx_command.recv() => trace!("ignoring {} for account {}, it isn't running", command.topic, account.name()),
+            }
+        }
+        backoff = (backoff * 2).min(ACCOUNT_RETRY_MAX_SECS);
+
+        let cfg = rx_reload.borrow_and_update().cfg.clone();
+        match FireboardWatcher::new(&cfg, &account, device_claims.clone(), tx_mqtt.clone()).await {
+            Ok(watcher) => {
+                info!("account {} started", account.name());
+                return poll_account(watcher, rx_reload, rx_command).await;
             }
             Err(e) => {
-                error!("mqtt error: {e:?}");
-                process::exit(3);
+                error!("Error setting up FireboardWatcher for account {}: {:?}", account.name(), e);
+                error = e;
+            }
+        }
+    }
+}
+
+/// Polls an account's devices forever, handling commands as they arrive in between
+async fn poll_account(
+    mut watcher: FireboardWatcher,
+    mut rx_reload: watch::Receiver<Reload>,
+    mut rx_command: mpsc::Receiver<MQTTCommand>,
+) {
+    loop {
+        watcher.update().await;
+        if let Some(usage) = memory_stats() {
+            info!(
+                "Current physical memory usage: {}",
+                human_bytes(usage.physical_mem as u32)
+            );
+            // info!("Current virtual memory usage: {}", usage.virtual_mem);
+        }
+        debug!("there are {} devices online", watcher.online_device_count());
+        // the fireboard cloud api has a rate limit of 200 requests per hour, so the interval
+        // depends on whether devices are online, drive support, and how many requests the
+        // poll took
+        let sleep_duration = watcher.poll_interval();
+        debug!(
+            "updating from fireboard cloud api in {} seconds",
+            sleep_duration
+        );
+        // commands (e.g. a new drive setpoint) are handled as they arrive
+        // without delaying the next scheduled update
+        let next_update = time::Instant::now() + time::Duration::from_secs(sleep_duration);
+        loop {
+            tokio::select! {
+                _ = sleep_until(next_update) => break,
+                Some(command) = rx_command.recv() => watcher.handle_command(command).await,
+                Ok(()) = rx_reload.changed() => {
+                    let reload = rx_reload.borrow_and_update().clone();
+                    watcher.reload(&reload.cfg, reload.reconnected).await;
+                    // poll straight away with the new settings
+                    break;
+                }
             }
         }
     }
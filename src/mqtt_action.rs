@@ -14,7 +14,6 @@ pub enum MQTTAction {
         payload: Bytes,
         props: Option<PublishProperties>,
     },
-    #[allow(dead_code)]
     Subscribe {
         topic: String,
         qos: QoS,
@@ -29,3 +28,10 @@ pub enum MQTTAction {
 
 unsafe impl Send for MQTTAction {}
 unsafe impl Sync for MQTTAction {}
+
+/// A message received on one of the command topics the bridge subscribes to
+#[derive(Debug, Clone)]
+pub struct MQTTCommand {
+    pub topic: String,
+    pub payload: Bytes,
+}
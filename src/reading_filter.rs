@@ -0,0 +1,176 @@
+//! # Reading Filter
+//!
+//! Probes occasionally report nonsense: a sudden 0, a one-off spike of hundreds of degrees
+//! or a sentinel when a probe is half unplugged. Every reading goes through this filter
+//! before it's published or used for anything else. Readings outside the plausible range
+//! are always rejected. Readings that jump too far from the median of the last few
+//! accepted readings are rejected too, unless a few in a row agree with each other, in
+//! which case the temperature really did change (e.g. a probe moved from the counter into
+//! the pit).
+use std::collections::VecDeque;
+
+/// how many accepted readings the median is taken over
+const MEDIAN_READINGS: usize = 5;
+
+/// how many rejected readings in a row that agree with each other are taken as the new
+/// normal
+const SPIKE_CONFIRM_READINGS: usize = 3;
+
+/// Everything needed to decide whether a reading is plausible, in the device's output unit
+#[derive(Debug, Clone, Copy)]
+pub struct ReadingCriteria {
+    pub min: f32,
+    pub max: f32,
+    /// the furthest a reading can be from the recent median
+    pub max_jump: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rejection {
+    OutOfRange,
+    Spike,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ReadingFilter {
+    recent: VecDeque<f32>,
+    /// spikes rejected in a row, which may yet turn out to be a real change
+    pending: Vec<f32>,
+    rejected: u64,
+}
+
+impl ReadingFilter {
+    /// Runs a reading through the filter, returning why it was rejected if it was
+    pub fn check(&mut self, temp: f32, criteria: &ReadingCriteria) -> Result<(), Rejection> {
+        if !temp.is_finite() || temp < criteria.min || temp > criteria.max {
+            self.rejected += 1;
+            return Err(Rejection::OutOfRange);
+        }
+
+        let spike = self
+            .median()
+            .is_some_and(|median| (temp - median).abs() > criteria.max_jump);
+        if !spike {
+            self.pending.clear();
+            self.accept(temp);
+            return Ok(());
+        }
+
+        self.pending.push(temp);
+        let (low, high) = self
+            .pending
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(low, high), temp| {
+                (low.min(*temp), high.max(*temp))
+            });
+        if self.pending.len() >= SPIKE_CONFIRM_READINGS && high - low <= criteria.max_jump {
+            // the readings agree with each other, so this is a real change
+            self.recent = self.pending.drain(..).collect();
+            return Ok(());
+        }
+        if self.pending.len() >= SPIKE_CONFIRM_READINGS {
+            self.pending.remove(0);
+        }
+        self.rejected += 1;
+        Err(Rejection::Spike)
+    }
+
+    /// how many readings have been rejected since the bridge started
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// forgets the recent readings, e.g. when the probe is unplugged
+    pub fn reset(&mut self) {
+        self.recent.clear();
+        self.pending.clear();
+    }
+
+    fn accept(&mut self, temp: f32) {
+        self.recent.push_back(temp);
+        if self.recent.len() > MEDIAN_READINGS {
+            self.recent.pop_front();
+        }
+    }
+
+    fn median(&self) -> Option<f32> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f32> = self.recent.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        Some(sorted[sorted.len() / 2])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CRITERIA: ReadingCriteria = ReadingCriteria {
+        min: -40.0,
+        max: 1000.0,
+        max_jump: 50.0,
+    };
+
+    /// A filter that has accepted a few readings at `temp`
+    fn settled(temp: f32) -> ReadingFilter {
+        let mut filter = ReadingFilter::default();
+        for _ in 0..MEDIAN_READINGS {
+            filter.check(temp, &CRITERIA).unwrap();
+        }
+        filter
+    }
+
+    #[test]
+    fn implausible_readings() {
+        let mut filter = settled(225.0);
+        assert_eq!(filter.check(-100.0, &CRITERIA), Err(Rejection::OutOfRange));
+        assert_eq!(filter.check(2000.0, &CRITERIA), Err(Rejection::OutOfRange));
+        assert_eq!(
+            filter.check(f32::NAN, &CRITERIA),
+            Err(Rejection::OutOfRange)
+        );
+        assert_eq!(filter.rejected(), 3);
+        // they don't move the median
+        assert_eq!(filter.check(230.0, &CRITERIA), Ok(()));
+    }
+
+    #[test]
+    fn one_off_spike() {
+        let mut filter = settled(225.0);
+        assert_eq!(filter.check(0.0, &CRITERIA), Err(Rejection::Spike));
+        assert_eq!(filter.check(226.0, &CRITERIA), Ok(()));
+        assert_eq!(filter.rejected(), 1);
+    }
+
+    #[test]
+    fn agreeing_readings_are_a_real_change() {
+        // a probe moved from the counter into the pit
+        let mut filter = settled(70.0);
+        assert_eq!(filter.check(250.0, &CRITERIA), Err(Rejection::Spike));
+        assert_eq!(filter.check(255.0, &CRITERIA), Err(Rejection::Spike));
+        assert_eq!(filter.check(252.0, &CRITERIA), Ok(()));
+        // the new level is what later readings are compared with
+        assert_eq!(filter.check(254.0, &CRITERIA), Ok(()));
+        assert_eq!(filter.check(70.0, &CRITERIA), Err(Rejection::Spike));
+    }
+
+    #[test]
+    fn disagreeing_spikes_never_confirm() {
+        // a half unplugged probe flapping between nonsense values
+        let mut filter = settled(225.0);
+        for temp in [0.0, 600.0, 0.0, 600.0, 0.0, 600.0] {
+            assert_eq!(filter.check(temp, &CRITERIA), Err(Rejection::Spike));
+        }
+        assert_eq!(filter.rejected(), 6);
+        assert_eq!(filter.check(225.0, &CRITERIA), Ok(()));
+    }
+
+    #[test]
+    fn reset_forgets_the_old_level() {
+        let mut filter = settled(225.0);
+        filter.reset();
+        assert_eq!(filter.check(70.0, &CRITERIA), Ok(()));
+    }
+}
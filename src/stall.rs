@@ -0,0 +1,203 @@
+//! # Stall Detection
+//!
+//! Large cuts (brisket, pork shoulder) stop rising for hours somewhere around 150-170°F
+//! while moisture evaporates off the surface. A channel is considered stalled once its
+//! trend has been flat for a while inside the stall band, and the stall ends when the
+//! channel starts climbing again or leaves the band.
+use chrono::{DateTime, Duration, Local};
+
+/// how much faster than the flat rate a channel has to climb before the stall is over,
+/// so a rate hovering right at the limit doesn't start and end stalls every poll
+const STALL_EXIT_RATE_FACTOR: f32 = 2.0;
+
+/// Everything needed to decide whether a channel is stalled, in the channel's unit
+#[derive(Debug, Clone, Copy)]
+pub struct StallCriteria {
+    pub band_min: f32,
+    pub band_max: f32,
+    /// the fastest a channel can be rising, in degrees per hour, and still count as flat
+    pub max_rate_per_hour: f32,
+    pub min_duration: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StallTransition {
+    Started {
+        since: DateTime<Local>,
+    },
+    Ended {
+        since: DateTime<Local>,
+        duration: Duration,
+    },
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct StallDetector {
+    stalled_since: Option<DateTime<Local>>,
+    flat_since: Option<DateTime<Local>>,
+}
+
+impl StallDetector {
+    /// Feeds a new reading and the channel's current trend into the detector, returning a
+    /// transition if the channel entered or left the stall
+    pub fn evaluate(
+        &mut self,
+        temp: f32,
+        rate_per_hour: Option<f32>,
+        criteria: &StallCriteria,
+        now: DateTime<Local>,
+    ) -> Option<StallTransition> {
+        let in_band = temp >= criteria.band_min && temp <= criteria.band_max;
+
+        if let Some(since) = self.stalled_since {
+            let climbing = rate_per_hour
+                .is_some_and(|rate| rate > criteria.max_rate_per_hour * STALL_EXIT_RATE_FACTOR);
+            if !in_band || climbing {
+                self.reset();
+                return Some(StallTransition::Ended {
+                    since,
+                    duration: now - since,
+                });
+            }
+            return None;
+        }
+
+        let flat = rate_per_hour.is_some_and(|rate| rate.abs() <= criteria.max_rate_per_hour);
+        if !(in_band && flat) {
+            self.flat_since = None;
+            return None;
+        }
+
+        let flat_since = *self.flat_since.get_or_insert(now);
+        if now - flat_since >= criteria.min_duration {
+            // the stall really started when the channel went flat, not when we were sure of it
+            self.stalled_since = Some(flat_since);
+            return Some(StallTransition::Started { since: flat_since });
+        }
+        None
+    }
+
+    pub fn stalled(&self) -> bool {
+        self.stalled_since.is_some()
+    }
+
+    /// how long the channel has been stalled, if it is
+    pub fn duration(&self, now: DateTime<Local>) -> Option<Duration> {
+        self.stalled_since.map(|since| now - since)
+    }
+
+    pub fn reset(&mut self) {
+        self.stalled_since = None;
+        self.flat_since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel_history::ChannelHistory;
+
+    const CRITERIA: StallCriteria = StallCriteria {
+        band_min: 150.0,
+        band_max: 170.0,
+        max_rate_per_hour: 2.0,
+        min_duration: Duration::minutes(30),
+    };
+
+    /// A detector that has been flat at 160 long enough to stall, and when it went flat
+    fn stalled() -> (StallDetector, DateTime<Local>) {
+        let start = Local::now();
+        let mut detector = StallDetector::default();
+        assert_eq!(detector.evaluate(160.0, Some(0.5), &CRITERIA, start), None);
+        assert_eq!(
+            detector.evaluate(160.0, Some(0.5), &CRITERIA, start + Duration::minutes(30)),
+            Some(StallTransition::Started { since: start })
+        );
+        (detector, start)
+    }
+
+    #[test]
+    fn brief_plateau_is_not_a_stall() {
+        let start = Local::now();
+        let mut detector = StallDetector::default();
+        detector.evaluate(155.0, Some(1.0), &CRITERIA, start);
+        // climbing again after 20 minutes starts the flat time over
+        detector.evaluate(156.0, Some(10.0), &CRITERIA, start + Duration::minutes(20));
+        let flat_again = start + Duration::minutes(25);
+        detector.evaluate(157.0, Some(1.0), &CRITERIA, flat_again);
+        assert_eq!(
+            detector.evaluate(157.0, Some(1.0), &CRITERIA, start + Duration::minutes(40)),
+            None
+        );
+        assert_eq!(
+            detector.evaluate(
+                157.0,
+                Some(1.0),
+                &CRITERIA,
+                flat_again + Duration::minutes(30)
+            ),
+            Some(StallTransition::Started { since: flat_again })
+        );
+    }
+
+    #[test]
+    fn leaving_the_band_ends_it() {
+        let (mut detector, start) = stalled();
+        // still flat, but above the band
+        let now = start + Duration::minutes(90);
+        assert_eq!(
+            detector.evaluate(171.0, Some(0.5), &CRITERIA, now),
+            Some(StallTransition::Ended {
+                since: start,
+                duration: Duration::minutes(90)
+            })
+        );
+        assert!(!detector.stalled());
+    }
+
+    #[test]
+    fn climbing_ends_it_past_the_exit_rate() {
+        let (mut detector, start) = stalled();
+        // too fast to start a stall, but not fast enough to end one
+        let now = start + Duration::minutes(60);
+        assert_eq!(detector.evaluate(162.0, Some(3.5), &CRITERIA, now), None);
+        assert!(detector.stalled());
+        assert_eq!(detector.duration(now), Some(Duration::minutes(60)));
+
+        let now = start + Duration::minutes(70);
+        assert_eq!(
+            detector.evaluate(164.0, Some(4.5), &CRITERIA, now),
+            Some(StallTransition::Ended {
+                since: start,
+                duration: Duration::minutes(70)
+            })
+        );
+    }
+
+    #[test]
+    fn stall_from_a_channel_history() {
+        // climbing 1 degree a minute up to 158, then creeping up a degree an hour
+        let start = Local::now();
+        let mut history = ChannelHistory::new(Duration::minutes(10));
+        let mut detector = StallDetector::default();
+        let mut transitions = vec![];
+        for minute in 0..120 {
+            let now = start + Duration::minutes(minute);
+            let temp = if minute < 30 {
+                128.0 + minute as f32
+            } else {
+                158.0 + (minute - 30) as f32 / 60.0
+            };
+            history.push(now, temp);
+            let rate = history
+                .rate_per_minute(Duration::minutes(5), now)
+                .map(|rate| rate * 60.0);
+            transitions.extend(detector.evaluate(temp, rate, &CRITERIA, now));
+        }
+        // the rate settles within the 5 minute window after the climb ends
+        let [StallTransition::Started { since }] = transitions[..] else {
+            panic!("expected a single stall, got {:?}", transitions);
+        };
+        assert!(since > start + Duration::minutes(30) && since <= start + Duration::minutes(36));
+    }
+}
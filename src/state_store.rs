@@ -0,0 +1,118 @@
+//! # State Store
+//!
+//! Settings changed at runtime over mqtt (e.g. alarm thresholds) are kept in a small json
+//! file in the state directory so they survive restarts.
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::fireboard_api::DegreeType;
+use crate::temperature::Temperature;
+
+const STATE_FILE_NAME: &str = "state.json";
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct ChannelSettings {
+    #[serde(default)]
+    pub alarm_min: Option<f32>,
+    #[serde(default)]
+    pub alarm_max: Option<f32>,
+    /// the temperature the food on this channel is done at
+    #[serde(default)]
+    pub target: Option<f32>,
+    /// the unit the temperatures above are in, missing in state files written before the
+    /// output unit was configurable, those are in the device's unit
+    #[serde(default)]
+    pub unit: Option<DegreeType>,
+}
+
+impl ChannelSettings {
+    /// The settings with every temperature converted into `unit`
+    pub fn to_unit(self, unit: DegreeType, device_unit: DegreeType) -> ChannelSettings {
+        let saved_unit = self.unit.unwrap_or(device_unit);
+        let convert = |temp: Option<f32>| {
+            temp.map(|temp| Temperature::new(temp, saved_unit).to_unit(unit).value())
+        };
+        ChannelSettings {
+            alarm_min: convert(self.alarm_min),
+            alarm_max: convert(self.alarm_max),
+            target: convert(self.target),
+            unit: Some(unit),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PersistedState {
+    /// keyed by the channel's unique id, e.g. `FB123456_channel_1`
+    #[serde(default)]
+    channels: HashMap<String, ChannelSettings>,
+}
+
+pub struct StateStore {
+    path: PathBuf,
+    state: PersistedState,
+}
+
+impl StateStore {
+    /// Loads the state file from `state_dir`. A missing or unreadable file is not fatal,
+    /// we just start over with empty state.
+    pub fn load(state_dir: &str) -> StateStore {
+        let path = PathBuf::from(state_dir).join(STATE_FILE_NAME);
+        let state = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str::<PersistedState>(&contents).unwrap_or_else(|e| {
+                warn!(
+                    "unable to parse state file {}, starting fresh: {}",
+                    path.display(),
+                    e
+                );
+                PersistedState::default()
+            }),
+            Err(e) => {
+                debug!("no state loaded from {}: {}", path.display(), e);
+                PersistedState::default()
+            }
+        };
+        StateStore { path, state }
+    }
+
+    pub fn channel(&self, channel_id: &str) -> ChannelSettings {
+        self.state
+            .channels
+            .get(channel_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Applies `update` to a channel's settings and writes the state file
+    pub fn update_channel<F>(&mut self, channel_id: &str, update: F) -> Result<ChannelSettings>
+    where
+        F: FnOnce(&mut ChannelSettings),
+    {
+        let settings = self
+            .state
+            .channels
+            .entry(channel_id.to_string())
+            .or_default();
+        update(settings);
+        let settings = *settings;
+        self.save()?;
+        Ok(settings)
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // write to a temp file first so a crash never leaves a half written state file
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(&self.state)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        debug!("state saved to {}", self.path.display());
+        Ok(())
+    }
+}
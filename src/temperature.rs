@@ -0,0 +1,54 @@
+//! # Temperature
+//!
+//! Fireboard reports every temperature in the unit the device is configured for, which can
+//! differ from device to device. Readings are wrapped in a [`Temperature`] as soon as they
+//! leave the api so the unit travels with the value, and are only turned back into a bare
+//! number (in the configured output unit) when published.
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::fireboard_api::DegreeType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Temperature {
+    value: f32,
+    unit: DegreeType,
+}
+
+impl Temperature {
+    pub fn new(value: f32, unit: DegreeType) -> Temperature {
+        Temperature { value, unit }
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// the same temperature expressed in `unit`
+    pub fn to_unit(self, unit: DegreeType) -> Temperature {
+        let value = match (self.unit, unit) {
+            (DegreeType::Celcius, DegreeType::Fahrenheit) => self.value * 9.0 / 5.0 + 32.0,
+            (DegreeType::Fahrenheit, DegreeType::Celcius) => (self.value - 32.0) * 5.0 / 9.0,
+            _ => self.value,
+        };
+        Temperature { value, unit }
+    }
+}
+
+/// Formats just the value (rounded to a tenth of a degree), as published over mqtt
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", (self.value * 10.0).round() / 10.0)
+    }
+}
+
+/// Converts a temperature difference (or a rate of change) between units. Unlike a
+/// temperature this only scales, there is no offset.
+pub fn convert_delta(delta: f32, from: DegreeType, to: DegreeType) -> f32 {
+    match (from, to) {
+        (DegreeType::Celcius, DegreeType::Fahrenheit) => delta * 9.0 / 5.0,
+        (DegreeType::Fahrenheit, DegreeType::Celcius) => delta * 5.0 / 9.0,
+        _ => delta,
+    }
+}
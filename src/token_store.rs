@@ -0,0 +1,97 @@
+//! # Token Store
+//!
+//! The token from the last login to the fireboard api is kept in the state directory, so
+//! a restart can reuse it instead of logging in again. The file is only readable by us.
+use std::fs::{self, OpenOptions, Permissions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+const TOKEN_FILE_NAME: &str = "auth_token.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedToken {
+    /// the account the token was issued for, so changing the email doesn't reuse it
+    email: String,
+    key: String,
+}
+
+pub struct TokenStore {
+    path: PathBuf,
+}
+
+impl TokenStore {
+    pub fn new(state_dir: &str) -> TokenStore {
+        TokenStore {
+            path: PathBuf::from(state_dir).join(TOKEN_FILE_NAME),
+        }
+    }
+
+    /// The saved token, if there is one for `email`
+    pub fn load(&self, email: &str) -> Option<String> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                debug!("no auth token loaded from {}: {}", self.path.display(), e);
+                return None;
+            }
+        };
+        match serde_json::from_str::<PersistedToken>(&contents) {
+            Ok(token) if token.email == email => Some(token.key),
+            Ok(_) => {
+                debug!(
+                    "auth token in {} is for another account",
+                    self.path.display()
+                );
+                None
+            }
+            Err(e) => {
+                warn!(
+                    "unable to parse auth token file {}: {}",
+                    self.path.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, email: &str, key: &str) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let token = PersistedToken {
+            email: email.to_string(),
+            key: key.to_string(),
+        };
+        // write to a temp file first so a crash never leaves a half written token file. The
+        // mode only applies to new files, so it's set again in case the temp file was left over
+        let tmp_path = self.path.with_extension("json.tmp");
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp_path)?;
+        file.set_permissions(Permissions::from_mode(0o600))?;
+        file.write_all(serde_json::to_string(&token)?.as_bytes())?;
+        fs::rename(&tmp_path, &self.path)?;
+        debug!("auth token saved to {}", self.path.display());
+        Ok(())
+    }
+
+    /// Forgets a token the api rejected
+    pub fn clear(&self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            debug!(
+                "unable to remove auth token file {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
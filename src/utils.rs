@@ -1,7 +1,20 @@
+use log::warn;
 use serde::{Deserialize, Deserializer};
 
+/// converts a 0-1 fraction into a 0-100 percentage, clamping (with a warning) anything
+/// outside that range
 pub fn f32_to_u8_pct(value: f32) -> u8 {
-    f32::round(value * 100.0) as u8
+    let pct = f32::round(value * 100.0);
+    if (0.0..=100.0).contains(&pct) {
+        return pct as u8;
+    }
+    // NaN ends up as 0
+    let clamped = pct.clamp(0.0, 100.0) as u8;
+    warn!(
+        "expected a fraction between 0 and 1 but got {}, using {}%",
+        value, clamped
+    );
+    clamped
 }
 
 pub fn deserialize_empty_object<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
@@ -26,3 +39,23 @@ where
         Err(e) => Err(e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fraction_to_pct() {
+        assert_eq!(f32_to_u8_pct(0.0), 0);
+        assert_eq!(f32_to_u8_pct(0.424), 42);
+        assert_eq!(f32_to_u8_pct(0.996), 100);
+        assert_eq!(f32_to_u8_pct(1.0), 100);
+    }
+
+    #[test]
+    fn out_of_range_is_clamped() {
+        assert_eq!(f32_to_u8_pct(-0.2), 0);
+        assert_eq!(f32_to_u8_pct(1.3), 100);
+        assert_eq!(f32_to_u8_pct(f32::NAN), 0);
+    }
+}